@@ -25,6 +25,21 @@ pub use camera::*;
 mod material;
 pub use material::*;
 
+mod microfacet;
+pub use microfacet::*;
+
+mod principled;
+pub use principled::*;
+
+mod composite;
+pub use composite::*;
+
+mod bump;
+pub use bump::*;
+
+mod error;
+pub use error::*;
+
 mod utils;
 pub use utils::*;
 
@@ -37,6 +52,42 @@ pub use axis::*;
 mod bvh;
 pub use bvh::*;
 
+mod bvh4;
+pub use bvh4::*;
+
+mod bvh_cache;
+pub use bvh_cache::*;
+
+mod tlas;
+pub use tlas::*;
+
+mod instance;
+pub use instance::*;
+
+mod medium;
+pub use medium::*;
+
+mod density;
+pub use density::*;
+
+mod onb;
+pub use onb::*;
+
+mod environment;
+pub use environment::*;
+
+mod light;
+pub use light::*;
+
+mod ies;
+pub use ies::*;
+
+mod sky;
+pub use sky::*;
+
+mod environment_map;
+pub use environment_map::*;
+
 mod triangle;
 pub use triangle::*;
 
@@ -46,6 +97,9 @@ pub use texture::*;
 mod noise;
 pub use noise::*;
 
+mod procedural;
+pub use procedural::*;
+
 mod quad;
 pub use quad::*;
 
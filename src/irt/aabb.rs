@@ -59,15 +59,21 @@ impl Aabb {
         self.max = self.max.max(point);
     }
 
-    pub fn hit(&self, ray: &Ray, t_interval: &Interval) -> bool {
+    /// Returns the `t` at which the ray enters the AABB,
+    /// clamped to `t_interval`, or `None` if the ray misses it.
+    pub fn hit(&self, ray: &Ray, t_interval: &Interval) -> Option<f32> {
         let inverse_ray_direction = 1. / ray.direction;
         let t_0 = (self.min - ray.origin) * inverse_ray_direction;
         let t_1 = (self.max - ray.origin) * inverse_ray_direction;
 
-        let t_min = t_0.min(t_1).max_component();
-        let t_max = t_0.max(t_1).min_component();
+        let t_enter = t_interval.min.max(t_0.min(t_1).max_component());
+        let t_exit = t_interval.max.min(t_0.max(t_1).min_component());
 
-        return t_interval.min.max(t_min) <= t_interval.max.min(t_max);
+        return if t_enter <= t_exit {
+            Some(t_enter)
+        } else {
+            None
+        };
     }
 
     pub fn area(&self) -> f32 {
@@ -89,3 +95,39 @@ impl AddAssign for Aabb {
         self.max = self.max.max(other.max);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_returns_entry_t() {
+        let aabb = Aabb::new(Point::new(-1., -1., -1.), Point::new(1., 1., 1.));
+        let ray = Ray::new(Point::new(0., 0., -5.), Vec3::new(0., 0., 1.));
+
+        let t = aabb.hit(&ray, &Interval::new(0., f32::INFINITY));
+        assert_eq!(t, Some(4.));
+    }
+
+    #[test]
+    fn hit_from_inside_returns_interval_min() {
+        let aabb = Aabb::new(Point::new(-1., -1., -1.), Point::new(1., 1., 1.));
+        let ray = Ray::new(Point::new(0., 0., 0.), Vec3::new(0., 0., 1.));
+
+        let t = aabb.hit(&ray, &Interval::new(0.001, f32::INFINITY));
+        assert_eq!(t, Some(0.001));
+    }
+
+    #[test]
+    fn miss_and_culled_return_none() {
+        let aabb = Aabb::new(Point::new(-1., -1., -1.), Point::new(1., 1., 1.));
+        let missing_ray = Ray::new(Point::new(0., 5., -5.), Vec3::new(0., 0., 1.));
+        let ray = Ray::new(Point::new(0., 0., -5.), Vec3::new(0., 0., 1.));
+
+        assert_eq!(
+            aabb.hit(&missing_ray, &Interval::new(0., f32::INFINITY)),
+            None
+        );
+        assert_eq!(aabb.hit(&ray, &Interval::new(0., 3.)), None);
+    }
+}
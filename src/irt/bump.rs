@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use crate::irt::{Color, Hit, Material, Ray, Texture, UnitVec3, Vec3};
+
+/// Returns `hit` shaded with `normal` instead of its geometric normal,
+/// unless `normal` faces away from `ray_in`, which would leave the surface black.
+fn shade<'a>(ray_in: &Ray, hit: &Hit<'a>, normal: Vec3) -> Hit<'a> {
+    let normal = normal.normalize();
+    if normal.as_vec3().dot(ray_in.direction) >= 0. {
+        return *hit;
+    }
+    return Hit { normal, ..*hit };
+}
+
+/// Adds surface detail to a material with a tangent-space normal map, such as an image
+/// whose red, green and blue channels give the `x`, `y` and `z` components of the normal,
+/// remapped from -1..1 to 0..1. `x` follows [`Hit::tangent`] and `z` the normal.
+#[derive(Debug)]
+pub struct NormalMap {
+    material: Arc<dyn Material>,
+    normal_map: Box<dyn Texture>,
+    strength: f32,
+}
+impl NormalMap {
+    pub fn new(material: Arc<dyn Material>, normal_map: Box<dyn Texture>) -> Self {
+        return Self {
+            material,
+            normal_map,
+            strength: 1.,
+        };
+    }
+
+    /// Scales the tilt of the normals, 0 leaving the surface flat.
+    pub fn with_strength(self, strength: f32) -> Self {
+        return Self { strength, ..self };
+    }
+
+    fn shading_hit<'a>(&self, ray_in: &Ray, hit: &Hit<'a>) -> Hit<'a> {
+        let color = self.normal_map.value_at(hit);
+        let local = Vec3::new(
+            self.strength * (2. * color.r - 1.),
+            self.strength * (2. * color.g - 1.),
+            2. * color.b - 1.,
+        );
+        return shade(ray_in, hit, hit.frame().local(local));
+    }
+}
+impl Material for NormalMap {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
+        return self
+            .material
+            .scatter(ray_in, &self.shading_hit(ray_in, hit));
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit: &Hit) -> Color {
+        return self
+            .material
+            .emitted(ray_in, &self.shading_hit(ray_in, hit));
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> Option<Color> {
+        return self
+            .material
+            .eval(ray_in, &self.shading_hit(ray_in, hit), direction);
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> f32 {
+        return self
+            .material
+            .pdf(ray_in, &self.shading_hit(ray_in, hit), direction);
+    }
+
+    fn scatter_specular(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color, bool)> {
+        return self
+            .material
+            .scatter_specular(ray_in, &self.shading_hit(ray_in, hit));
+    }
+}
+
+/// Adds surface detail to a material by tilting its normals along the slopes of a height
+/// texture, which reads the red channel.
+///
+/// The slopes are measured over a small step along the tangents of the surface, stepping
+/// both the point and the texture coordinates, so both procedural textures such as
+/// [`NoiseTexture`](crate::irt::NoiseTexture) and image textures can be used.
+/// Texture coordinates are stepped by the step over the lengths of the tangents, so that
+/// heights are in world units: the same image bumps a large sphere more gently than a
+/// small one.
+#[derive(Debug)]
+pub struct BumpMap {
+    material: Arc<dyn Material>,
+    height: Box<dyn Texture>,
+    /// The height given by a texture value of 1, in world units.
+    scale: f32,
+}
+impl BumpMap {
+    /// The step over which the slopes of the height texture are measured.
+    const STEP: f32 = 1e-3;
+
+    pub fn new(material: Arc<dyn Material>, height: Box<dyn Texture>, scale: f32) -> Self {
+        return Self {
+            material,
+            height,
+            scale,
+        };
+    }
+
+    fn shading_hit<'a>(&self, ray_in: &Ray, hit: &Hit<'a>) -> Hit<'a> {
+        let height = |u: f32, v: f32, offset: Vec3| {
+            self.scale * self.height.value(u, v, hit.point + offset).r
+        };
+        // Steps the world distance `STEP` along each tangent, and the texture coordinates
+        // by as much as that moves them, giving the slope of the height in world units
+        let h = height(hit.u, hit.v, Vec3::new(0., 0., 0.));
+        let slope = |tangent: Vec3, du: f32, dv: f32| {
+            let length = tangent.length();
+            if length == 0. {
+                return Vec3::new(0., 0., 0.);
+            }
+            let direction = tangent / length;
+            let step = Self::STEP / length;
+            let stepped = height(hit.u + du * step, hit.v + dv * step, Self::STEP * direction);
+            return (stepped - h) / Self::STEP * direction;
+        };
+
+        let gradient = slope(hit.tangent, 1., 0.) + slope(hit.bitangent, 0., 1.);
+        let normal = hit.normal.as_vec3();
+        return shade(
+            ray_in,
+            hit,
+            normal - (gradient - gradient.dot(normal) * normal),
+        );
+    }
+}
+impl Material for BumpMap {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
+        return self
+            .material
+            .scatter(ray_in, &self.shading_hit(ray_in, hit));
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit: &Hit) -> Color {
+        return self
+            .material
+            .emitted(ray_in, &self.shading_hit(ray_in, hit));
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> Option<Color> {
+        return self
+            .material
+            .eval(ray_in, &self.shading_hit(ray_in, hit), direction);
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> f32 {
+        return self
+            .material
+            .pdf(ray_in, &self.shading_hit(ray_in, hit), direction);
+    }
+
+    fn scatter_specular(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color, bool)> {
+        return self
+            .material
+            .scatter_specular(ray_in, &self.shading_hit(ray_in, hit));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{approx_equals, Hittable, Interval, Lambertian, Point, Quad, Sphere};
+    use std::f32::consts::PI;
+
+    /// A height rising along `x` with a slope of 1.
+    #[derive(Debug)]
+    struct Ramp;
+    impl Texture for Ramp {
+        fn value(&self, _u: f32, _v: f32, point: Point) -> Color {
+            return Color::new(point.x, 0., 0.);
+        }
+    }
+
+    /// Returns a floor whose tangent is `x`, and a ray looking straight down at it.
+    fn floor() -> (Quad, Ray) {
+        let white = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let floor = Quad::new(
+            Point::new(-1., 0., 1.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 0., -2.),
+            white,
+        );
+        let ray = Ray::new(Point::new(0.1, 1., 0.1), Vec3::new(0., -1., 0.));
+        return (floor, ray);
+    }
+
+    #[test]
+    fn normal_map_tilts_normal_towards_tangent() {
+        let white = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let material = NormalMap::new(white, Box::new(Color::new(1., 0.5, 1.)));
+        let (floor, ray) = floor();
+        let hit = floor.hit(&ray, &mut Interval::new(0., 10.)).unwrap();
+        assert_eq!(hit.tangent, Vec3::new(2., 0., 0.));
+
+        let normal = material.shading_hit(&ray, &hit).normal.as_vec3();
+        assert!(approx_equals(normal.x, 0.5_f32.sqrt()));
+        assert!(approx_equals(normal.y, 0.5_f32.sqrt()));
+    }
+
+    /// A height equal to the `v` texture coordinate.
+    #[derive(Debug)]
+    struct Latitude;
+    impl Texture for Latitude {
+        fn value(&self, _u: f32, v: f32, _point: Point) -> Color {
+            return Color::new(v, 0., 0.);
+        }
+    }
+
+    #[test]
+    fn bump_map_heights_are_in_world_units() {
+        let white = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let bumps = BumpMap::new(white.clone(), Box::new(Latitude), 1.);
+
+        // The height rises by 1 over half a meridian, a slope of 1 / (π r) at the equator
+        for radius in [1., 10.] {
+            let sphere = Sphere::new(Point::new(0., 0., 0.), radius, white.clone());
+            let ray = Ray::new(Point::new(2. * radius, 0., 0.), Vec3::new(-1., 0., 0.));
+            let hit = sphere.hit(&ray, &mut Interval::new(0., 100.)).unwrap();
+
+            let normal = bumps.shading_hit(&ray, &hit).normal.as_vec3();
+            let expected = 1. / (PI * radius);
+            assert!((-normal.y / normal.x - expected).abs() < 0.01 * expected);
+        }
+    }
+
+    #[test]
+    fn bump_map_follows_height_slope() {
+        let white = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let flat = BumpMap::new(white.clone(), Box::new(0.5), 1.);
+        let ramp = BumpMap::new(white, Box::new(Ramp), 0.5);
+        let (floor, ray) = floor();
+        let hit = floor.hit(&ray, &mut Interval::new(0., 10.)).unwrap();
+
+        let normal = flat.shading_hit(&ray, &hit).normal.as_vec3();
+        assert_eq!(normal, Vec3::new(0., 1., 0.));
+
+        // A slope of 0.5 along x tilts the normal back by atan(0.5)
+        let normal = ramp.shading_hit(&ray, &hit).normal.as_vec3();
+        assert!(approx_equals(normal.x / normal.y, -0.5));
+        assert!(approx_equals(normal.z, 0.));
+    }
+}
@@ -2,7 +2,7 @@ use std::ops::Range;
 
 use itertools::partition;
 
-use crate::irt::{Aabb, Axis, Hit, Hittable, Interval, Matrix, Ray};
+use crate::irt::{Aabb, Axis, Hit, Hittable, Instance, Interval, Ray};
 
 struct Split {
     axis: Axis,
@@ -20,11 +20,18 @@ impl Split {
 }
 
 pub struct Bvh<T: Hittable> {
-    hittables: Vec<T>,
-    nodes: Vec<BvhNode>,
+    pub(super) hittables: Vec<T>,
+    pub(super) nodes: Vec<BvhNode>,
+    /// The SAH cost of the tree when it was built,
+    /// used to decide when a refitted tree should be rebuilt.
+    pub(super) build_cost: f32,
 }
 impl<T: Hittable> Bvh<T> {
-    fn children(&self, node_index: usize) -> Option<(&BvhNode, &BvhNode)> {
+    /// Initial capacity of the traversal stack. Front-to-back traversal
+    /// never holds more than one entry per level of the tree.
+    const STACK_CAPACITY: usize = 64;
+
+    pub(super) fn children(&self, node_index: usize) -> Option<(&BvhNode, &BvhNode)> {
         let node = &self.nodes[node_index];
 
         if node.is_leaf() {
@@ -47,6 +54,47 @@ impl<T: Hittable> Bvh<T> {
         };
     }
 
+    /// Returns the hittables of the BVH, in BVH order.
+    ///
+    /// After hittables have been moved, [`Bvh::refit`] must be called
+    /// for the BVH to be valid again.
+    pub fn hittables_mut(&mut self) -> &mut [T] {
+        return &mut self.hittables;
+    }
+
+    /// Recomputes the bounds of every node bottom-up, e.g. after the hittables have moved.
+    ///
+    /// The topology of the tree is kept, so the quality of the tree degrades
+    /// the more the hittables move relative to each other.
+    pub fn refit(&mut self) {
+        self.refit_node(0);
+    }
+
+    fn refit_node(&mut self, node_index: usize) {
+        let node = &self.nodes[node_index];
+        if !node.is_leaf() {
+            let left_index = node.left_first;
+            self.refit_node(left_index);
+            self.refit_node(left_index + 1);
+        }
+        self.recompute_bounds(node_index);
+    }
+
+    /// Refits the BVH, rebuilding it instead if the refitted tree's SAH cost
+    /// exceeds `max_cost_ratio` times the cost of the tree when it was built.
+    ///
+    /// Returns whether the BVH was rebuilt.
+    pub fn refit_or_rebuild(&mut self, max_cost_ratio: f32) -> bool {
+        self.refit();
+        if self.sah2(0) <= max_cost_ratio * self.build_cost {
+            return false;
+        }
+
+        let hittables = std::mem::take(&mut self.hittables);
+        *self = Self::new(hittables);
+        return true;
+    }
+
     pub fn rotate(&mut self) {
         let right_child_index = self.nodes[0].left_first + 1;
         let right_child_left_child_index = self.nodes[right_child_index].left_first;
@@ -160,46 +208,102 @@ impl<T: Hittable> Bvh<T> {
     pub fn new(hittables: Vec<T>) -> Self {
         let nodes = Vec::with_capacity(hittables.len() * 2 - 1);
 
-        let mut bvh = Self { hittables, nodes };
+        let mut bvh = Self {
+            hittables,
+            nodes,
+            build_cost: 0.,
+        };
         let root_node = BvhNode::new(&bvh, 0, bvh.hittables.len());
 
         bvh.nodes.push(root_node);
         bvh.subdivide(0);
         bvh.nodes.shrink_to_fit();
+        bvh.build_cost = bvh.sah2(0);
 
         return bvh;
     }
 
-    fn intersect(&self, ray: &Ray, t_interval: &mut Interval, node_index: usize) -> Option<Hit> {
-        let node = &self.nodes[node_index];
-        if !node.bounds.hit(ray, t_interval) {
-            return None;
-        }
+    /// Finds the closest hit with an iterative, front-to-back traversal.
+    ///
+    /// The nearer child of each interior node is visited first, and nodes
+    /// whose entry `t` lies beyond the closest hit so far are culled.
+    fn intersect(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        let root_t = self.nodes[0].bounds.hit(ray, t_interval)?;
 
-        if node.is_leaf() {
-            return self.hittables[node.hittable_range()].hit(ray, t_interval);
-        } else {
-            let hit_left = self.intersect(ray, t_interval, node.left_first);
-            let hit_right = self.intersect(ray, t_interval, node.left_first + 1);
-
-            return match (&hit_left, &hit_right) {
-                (Some(left), Some(right)) => {
-                    if left.t <= right.t {
-                        hit_left
+        let mut closest_hit: Option<Hit> = None;
+        let mut stack: Vec<(usize, f32)> = Vec::with_capacity(Self::STACK_CAPACITY);
+        stack.push((0, root_t));
+
+        while let Some((node_index, entry_t)) = stack.pop() {
+            if entry_t > t_interval.max {
+                continue;
+            }
+
+            let node = &self.nodes[node_index];
+            if node.is_leaf() {
+                // Hits are bounded by `t_interval`, so any hit is closer than the previous one
+                if let Some(hit) = self.hittables[node.hittable_range()].hit(ray, t_interval) {
+                    t_interval.max = hit.t;
+                    closest_hit = Some(hit);
+                }
+                continue;
+            }
+
+            let left_index = node.left_first;
+            let right_index = node.left_first + 1;
+            let left_t = self.nodes[left_index].bounds.hit(ray, t_interval);
+            let right_t = self.nodes[right_index].bounds.hit(ray, t_interval);
+
+            // The nearer child is pushed last so that it is popped first
+            match (left_t, right_t) {
+                (Some(left_t), Some(right_t)) => {
+                    if left_t <= right_t {
+                        stack.push((right_index, right_t));
+                        stack.push((left_index, left_t));
                     } else {
-                        hit_right
+                        stack.push((left_index, left_t));
+                        stack.push((right_index, right_t));
                     }
                 }
-                _ => hit_left.or(hit_right),
-            };
+                (Some(left_t), None) => stack.push((left_index, left_t)),
+                (None, Some(right_t)) => stack.push((right_index, right_t)),
+                (None, None) => {}
+            }
         }
+
+        return closest_hit;
+    }
+
+    /// Returns whether anything is hit within `t_interval`,
+    /// terminating at the first intersection found.
+    fn any_hit(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        let mut stack: Vec<usize> = Vec::with_capacity(Self::STACK_CAPACITY);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node.bounds.hit(ray, t_interval).is_none() {
+                continue;
+            }
+
+            if node.is_leaf() {
+                if self.hittables[node.hittable_range()].occluded(ray, t_interval) {
+                    return true;
+                }
+            } else {
+                stack.push(node.left_first + 1);
+                stack.push(node.left_first);
+            }
+        }
+
+        return false;
     }
 }
 #[derive(Debug)]
-struct BvhNode {
-    bounds: Aabb,
-    left_first: usize,
-    hittable_count: usize,
+pub(super) struct BvhNode {
+    pub(super) bounds: Aabb,
+    pub(super) left_first: usize,
+    pub(super) hittable_count: usize,
 }
 impl BvhNode {
     fn new<T: Hittable>(bvh: &Bvh<T>, left_first: usize, hittable_count: usize) -> Self {
@@ -210,12 +314,12 @@ impl BvhNode {
         };
     }
 
-    fn hittable_range(&self) -> Range<usize> {
+    pub(super) fn hittable_range(&self) -> Range<usize> {
         return self.left_first..self.left_first + self.hittable_count;
     }
 
     /// Returns whether the node is a leaf, i.e. whether it contains hittables
-    fn is_leaf(&self) -> bool {
+    pub(super) fn is_leaf(&self) -> bool {
         return self.hittable_count > 0;
     }
 }
@@ -225,44 +329,131 @@ impl<T: Hittable> Hittable for Bvh<T> {
         return self.nodes[0].bounds;
     }
 
-    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit> {
-        return self.intersect(ray, t_interval, 0);
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        return self.intersect(ray, t_interval);
     }
-}
 
-pub struct BVHInstance<'a, T: Hittable> {
-    bvh: &'a Bvh<T>,
-    /// Object-to-world
-    transform: Matrix,
-    /// World-to-object
-    inverse_transform: Matrix,
-    /// Bounds in world space. Primarily used for building a TLAS
-    bounds: Aabb,
-}
-impl<'a, T: Hittable> BVHInstance<'a, T> {
-    /// Creates a new BVH instance from a BVH and an object-to-world transform.
-    pub fn new(bvh: &'a Bvh<T>, transform: Matrix) -> Self {
-        return Self {
-            bvh,
-            transform,
-            inverse_transform: transform.inverse(),
-            bounds: transform * bvh.bounds(),
-        };
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        return self.any_hit(ray, t_interval);
     }
 }
 
-impl<T: Hittable> Hittable for BVHInstance<'_, T> {
-    fn bounds(&self) -> Aabb {
-        return self.bounds;
+/// An instance of a shared BVH, placed in the world by a transform.
+///
+/// As the instance itself is [`Hittable`], BVHs of instances can be instanced in turn,
+/// allowing multi-level instancing.
+pub type BVHInstance<T> = Instance<Bvh<T>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{
+        Color, Lambertian, Material, Point, Sphere, Texture, Triangle, UnitVec3, Vec3,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn traversal_matches_brute_force() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let spheres = || {
+            (0..200)
+                .map(|i| {
+                    let center = Point::new(
+                        (i % 7) as f32 * 1.3,
+                        (i % 11) as f32 * 0.9,
+                        (i % 13) as f32 * 1.1,
+                    );
+                    Sphere::new(center, 0.2 + (i % 5) as f32 * 0.15, material.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        let brute_force = spheres();
+        let bvh = Bvh::new(spheres());
+
+        for _ in 0..500 {
+            let ray = Ray::new(
+                Point::new(4., 5., -10.),
+                UnitVec3::random().as_vec3() + Vec3::new(0., 0., 1.),
+            );
+            let expected = brute_force.hit(&ray, &mut Interval::new(0.001, f32::INFINITY));
+            let actual = bvh.hit(&ray, &mut Interval::new(0.001, f32::INFINITY));
+
+            match (expected, actual) {
+                (Some(expected), Some(actual)) => assert_eq!(expected.t, actual.t),
+                (None, None) => {}
+                (expected, actual) => panic!("Expected {expected:?}, got {actual:?}"),
+            }
+
+            let interval = Interval::new(0.001, f32::INFINITY);
+            assert_eq!(
+                bvh.occluded(&ray, &interval),
+                brute_force.occluded(&ray, &interval)
+            );
+        }
     }
 
-    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit> {
-        let mut hit = self.bvh.hit(&(self.inverse_transform * *ray), t_interval)?;
+    #[test]
+    fn refit_tracks_moved_hittables() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let triangles = (0..50)
+            .map(|i| {
+                let x = i as f32;
+                Triangle::new(
+                    Point::new(x, 0., 0.),
+                    Point::new(x + 1., 0., 0.),
+                    Point::new(x, 1., 0.),
+                    material.clone(),
+                )
+            })
+            .collect();
+        let mut bvh = Bvh::new(triangles);
+
+        let offset = Vec3::new(0., 10., 0.);
+        for triangle in bvh.hittables_mut() {
+            triangle.set_vertices(
+                triangle.a + offset,
+                triangle.b + offset,
+                triangle.c + offset,
+            );
+        }
+        assert!(!bvh.refit_or_rebuild(1.5));
 
-        // The hit is in object space and must therefore be transformed to world space
-        hit.point = self.transform * hit.point;
-        hit.normal = (self.inverse_transform.transpose() * hit.normal.as_vec3()).normalize();
+        let ray = Ray::new(Point::new(25.25, 10.25, -5.), Vec3::new(0., 0., 1.));
+        let hit = bvh.hit(&ray, &mut Interval::new(0.001, f32::INFINITY));
+        assert_eq!(hit.map(|hit| hit.t), Some(5.));
+    }
 
-        return Some(hit);
+    #[test]
+    fn traversal_skips_masked_hits() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let hole: Arc<dyn Texture> = Arc::new(0.);
+        let triangle = |z: f32| {
+            Triangle::new(
+                Point::new(0., 0., z),
+                Point::new(1., 0., z),
+                Point::new(0., 1., z),
+                material.clone(),
+            )
+        };
+        let bvh = Bvh::new(vec![
+            triangle(1.).with_opacity(hole.clone()),
+            triangle(2.),
+            triangle(3.).with_opacity(hole.clone()),
+        ]);
+
+        let ray = Ray::new(Point::new(0.25, 0.25, 0.), Vec3::new(0., 0., 1.));
+        let hit = bvh.hit(&ray, &mut Interval::new(0.001, f32::INFINITY));
+        assert_eq!(hit.map(|hit| hit.t), Some(2.));
+        assert!(bvh.occluded(&ray, &Interval::new(0.001, f32::INFINITY)));
+
+        // Only masked triangles lie beyond the opaque one
+        assert!(!bvh.occluded(&ray, &Interval::new(2.5, f32::INFINITY)));
+
+        // Both sides of a fully masked sphere are skipped
+        let bubble = Sphere::new(Point::new(0., 0., 5.), 1., material).with_opacity(hole);
+        let ray = Ray::new(Point::new(0., 0., 0.), Vec3::new(0., 0., 1.));
+        assert!(bubble
+            .hit(&ray, &mut Interval::new(0.001, f32::INFINITY))
+            .is_none());
     }
 }
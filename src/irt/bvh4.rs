@@ -0,0 +1,431 @@
+use std::ops::Range;
+
+use crate::irt::{Aabb, Bvh, Hit, Hittable, Interval, Point, Ray};
+
+/// Number of children per node.
+const WIDTH: usize = 4;
+
+/// A BVH with four children per node, also known as a QBVH.
+///
+/// It is built by collapsing a binary [`Bvh`], pulling grandchildren up into
+/// their parent until each node holds four children. The bounds of the children
+/// are stored as a structure of arrays, so that all four can be tested against
+/// a ray at once with SIMD.
+pub struct Bvh4<T: Hittable> {
+    hittables: Vec<T>,
+    nodes: Vec<Bvh4Node>,
+    bounds: Aabb,
+}
+impl<T: Hittable> Bvh4<T> {
+    /// Initial capacity of the traversal stack.
+    const STACK_CAPACITY: usize = 64;
+
+    pub fn new(hittables: Vec<T>) -> Self {
+        return Self::from(Bvh::new(hittables));
+    }
+
+    /// Creates the wide node for the binary node at `binary_index`,
+    /// returning its index.
+    fn collapse(&mut self, bvh: &Bvh<T>, binary_index: usize) -> usize {
+        let node_index = self.nodes.len();
+        self.nodes.push(Bvh4Node::empty());
+
+        // Repeatedly replace the largest interior child by its own children
+        let mut children = match bvh.children(binary_index) {
+            Some(_) => {
+                let left_first = bvh.nodes[binary_index].left_first;
+                vec![left_first, left_first + 1]
+            }
+            None => vec![binary_index],
+        };
+        while children.len() < WIDTH {
+            let largest_interior = children
+                .iter()
+                .enumerate()
+                .filter(|(_, &child)| !bvh.nodes[child].is_leaf())
+                .max_by(|(_, &a), (_, &b)| {
+                    let area_a = bvh.nodes[a].bounds.area();
+                    let area_b = bvh.nodes[b].bounds.area();
+                    area_a.total_cmp(&area_b)
+                })
+                .map(|(position, _)| position);
+            let Some(position) = largest_interior else {
+                break;
+            };
+
+            let left_first = bvh.nodes[children.swap_remove(position)].left_first;
+            children.push(left_first);
+            children.push(left_first + 1);
+        }
+
+        for (slot, &child) in children.iter().enumerate() {
+            let child_node = &bvh.nodes[child];
+            let (first, count) = match child_node.is_leaf() {
+                true => (child_node.left_first, child_node.hittable_count),
+                false => (self.collapse(bvh, child), 0),
+            };
+            self.nodes[node_index].set_child(slot, child_node.bounds, first, count);
+        }
+
+        return node_index;
+    }
+
+    /// Tests the ray against the four children of `node`, returning the
+    /// entry `t` of each child, or infinity for children that are missed.
+    fn intersect_children(
+        node: &Bvh4Node,
+        ray: &PrecomputedRay,
+        t_interval: &Interval,
+    ) -> [f32; 4] {
+        #[cfg(target_arch = "x86_64")]
+        return intersect_children_sse(node, ray, t_interval);
+        #[cfg(not(target_arch = "x86_64"))]
+        return intersect_children_scalar(node, ray, t_interval);
+    }
+
+    /// Finds the closest hit with a front-to-back traversal,
+    /// similar to [`Bvh`]'s traversal.
+    fn intersect(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        let root_t = self.bounds.hit(ray, t_interval)?;
+        let precomputed_ray = PrecomputedRay::new(ray);
+
+        let mut closest_hit: Option<Hit> = None;
+        let mut stack: Vec<(Child, f32)> = Vec::with_capacity(Self::STACK_CAPACITY);
+        stack.push((Child::Node(0), root_t));
+
+        while let Some((child, entry_t)) = stack.pop() {
+            if entry_t > t_interval.max {
+                continue;
+            }
+
+            let node = match child {
+                Child::Leaf(range) => {
+                    // Hits are bounded by `t_interval`, so any hit is closer than the previous one
+                    if let Some(hit) = self.hittables[range].hit(ray, t_interval) {
+                        t_interval.max = hit.t;
+                        closest_hit = Some(hit);
+                    }
+                    continue;
+                }
+                Child::Node(node_index) => &self.nodes[node_index],
+            };
+
+            let entry_ts = Self::intersect_children(node, &precomputed_ray, t_interval);
+            // Insertion sort of the hit slots by decreasing entry `t`, on the stack
+            let mut hit_slots = [(0, 0.); WIDTH];
+            let mut hit_count = 0;
+            for slot in (0..WIDTH).filter(|&slot| entry_ts[slot] < f32::INFINITY) {
+                let mut i = hit_count;
+                while i > 0 && hit_slots[i - 1].1 < entry_ts[slot] {
+                    hit_slots[i] = hit_slots[i - 1];
+                    i -= 1;
+                }
+                hit_slots[i] = (slot, entry_ts[slot]);
+                hit_count += 1;
+            }
+
+            // The nearest child is pushed last so that it is popped first
+            for &(slot, entry_t) in &hit_slots[..hit_count] {
+                stack.push((node.child(slot), entry_t));
+            }
+        }
+
+        return closest_hit;
+    }
+
+    /// Returns whether anything is hit within `t_interval`,
+    /// terminating at the first intersection found.
+    fn any_hit(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        if self.bounds.hit(ray, t_interval).is_none() {
+            return false;
+        }
+        let precomputed_ray = PrecomputedRay::new(ray);
+
+        let mut stack: Vec<usize> = Vec::with_capacity(Self::STACK_CAPACITY);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            let entry_ts = Self::intersect_children(node, &precomputed_ray, t_interval);
+
+            for slot in (0..WIDTH).filter(|&slot| entry_ts[slot] < f32::INFINITY) {
+                match node.child(slot) {
+                    Child::Leaf(range) => {
+                        if self.hittables[range].occluded(ray, t_interval) {
+                            return true;
+                        }
+                    }
+                    Child::Node(child_index) => stack.push(child_index),
+                }
+            }
+        }
+
+        return false;
+    }
+}
+impl<T: Hittable> From<Bvh<T>> for Bvh4<T> {
+    fn from(bvh: Bvh<T>) -> Self {
+        let mut bvh4 = Self {
+            hittables: Vec::new(),
+            nodes: Vec::with_capacity(bvh.nodes.len() / 2 + 1),
+            bounds: bvh.bounds(),
+        };
+        bvh4.collapse(&bvh, 0);
+        bvh4.nodes.shrink_to_fit();
+        bvh4.hittables = bvh.hittables;
+
+        return bvh4;
+    }
+}
+
+impl<T: Hittable> Hittable for Bvh4<T> {
+    fn bounds(&self) -> Aabb {
+        return self.bounds;
+    }
+
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        return self.intersect(ray, t_interval);
+    }
+
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        return self.any_hit(ray, t_interval);
+    }
+}
+
+/// A child of a [`Bvh4Node`], either another node or a range of hittables.
+enum Child {
+    Node(usize),
+    Leaf(Range<usize>),
+}
+
+/// A node with the bounds of its four children stored as a structure of arrays.
+///
+/// Unused child slots have inverted, empty bounds so that they are never hit.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, align(64))]
+struct Bvh4Node {
+    min_x: [f32; WIDTH],
+    min_y: [f32; WIDTH],
+    min_z: [f32; WIDTH],
+    max_x: [f32; WIDTH],
+    max_y: [f32; WIDTH],
+    max_z: [f32; WIDTH],
+    /// The index of the child node for interior children,
+    /// or the index of the first hittable for leaf children.
+    first: [u32; WIDTH],
+    /// The number of hittables for leaf children, 0 for interior children.
+    count: [u32; WIDTH],
+}
+impl Bvh4Node {
+    fn empty() -> Self {
+        return Self {
+            min_x: [f32::INFINITY; WIDTH],
+            min_y: [f32::INFINITY; WIDTH],
+            min_z: [f32::INFINITY; WIDTH],
+            max_x: [f32::NEG_INFINITY; WIDTH],
+            max_y: [f32::NEG_INFINITY; WIDTH],
+            max_z: [f32::NEG_INFINITY; WIDTH],
+            first: [0; WIDTH],
+            count: [0; WIDTH],
+        };
+    }
+
+    fn set_child(&mut self, slot: usize, bounds: Aabb, first: usize, count: usize) {
+        self.min_x[slot] = bounds.min.x;
+        self.min_y[slot] = bounds.min.y;
+        self.min_z[slot] = bounds.min.z;
+        self.max_x[slot] = bounds.max.x;
+        self.max_y[slot] = bounds.max.y;
+        self.max_z[slot] = bounds.max.z;
+        self.first[slot] = first as u32;
+        self.count[slot] = count as u32;
+    }
+
+    fn child(&self, slot: usize) -> Child {
+        let first = self.first[slot] as usize;
+        return match self.count[slot] {
+            0 => Child::Node(first),
+            count => Child::Leaf(first..first + count as usize),
+        };
+    }
+}
+
+/// Ray data shared by all the box tests of a traversal.
+struct PrecomputedRay {
+    origin: Point,
+    inverse_direction: [f32; 3],
+    /// Per axis, whether the direction is negative, in which case
+    /// the ray enters through the max side of a box.
+    negative: [bool; 3],
+}
+impl PrecomputedRay {
+    fn new(ray: &Ray) -> Self {
+        let inverse_direction = 1. / ray.direction;
+        return Self {
+            origin: ray.origin,
+            inverse_direction: [
+                inverse_direction.x,
+                inverse_direction.y,
+                inverse_direction.z,
+            ],
+            negative: [
+                inverse_direction.x < 0.,
+                inverse_direction.y < 0.,
+                inverse_direction.z < 0.,
+            ],
+        };
+    }
+
+    /// Returns the bounds of `node` that the ray enters and exits through, per axis.
+    #[allow(clippy::type_complexity)]
+    fn near_far<'a>(&self, node: &'a Bvh4Node) -> ([&'a [f32; WIDTH]; 3], [&'a [f32; WIDTH]; 3]) {
+        let pick = |negative: bool, min: &'a [f32; WIDTH], max: &'a [f32; WIDTH]| match negative {
+            true => (max, min),
+            false => (min, max),
+        };
+        let (near_x, far_x) = pick(self.negative[0], &node.min_x, &node.max_x);
+        let (near_y, far_y) = pick(self.negative[1], &node.min_y, &node.max_y);
+        let (near_z, far_z) = pick(self.negative[2], &node.min_z, &node.max_z);
+
+        return ([near_x, near_y, near_z], [far_x, far_y, far_z]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn intersect_children_sse(
+    node: &Bvh4Node,
+    ray: &PrecomputedRay,
+    t_interval: &Interval,
+) -> [f32; 4] {
+    use std::arch::x86_64::*;
+
+    let (near, far) = ray.near_far(node);
+    let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+    let mut entry_ts = [0.; WIDTH];
+
+    // SAFETY: SSE is part of the x86_64 baseline, and all loads and stores
+    // are unaligned loads and stores of arrays with four elements.
+    unsafe {
+        let mut t_enter = _mm_set1_ps(t_interval.min);
+        let mut t_exit = _mm_set1_ps(t_interval.max);
+        for axis in 0..3 {
+            let origin = _mm_set1_ps(origin[axis]);
+            let inverse_direction = _mm_set1_ps(ray.inverse_direction[axis]);
+            let t_near = _mm_mul_ps(
+                _mm_sub_ps(_mm_loadu_ps(near[axis].as_ptr()), origin),
+                inverse_direction,
+            );
+            let t_far = _mm_mul_ps(
+                _mm_sub_ps(_mm_loadu_ps(far[axis].as_ptr()), origin),
+                inverse_direction,
+            );
+            // A NaN from `0 * inf` is ignored, as `_mm_max_ps` and `_mm_min_ps`
+            // return the second operand when either operand is NaN
+            t_enter = _mm_max_ps(t_near, t_enter);
+            t_exit = _mm_min_ps(t_far, t_exit);
+        }
+
+        let hit_mask = _mm_cmple_ps(t_enter, t_exit);
+        let result = _mm_or_ps(
+            _mm_and_ps(hit_mask, t_enter),
+            _mm_andnot_ps(hit_mask, _mm_set1_ps(f32::INFINITY)),
+        );
+        _mm_storeu_ps(entry_ts.as_mut_ptr(), result);
+    }
+
+    return entry_ts;
+}
+
+#[cfg(any(not(target_arch = "x86_64"), test))]
+fn intersect_children_scalar(
+    node: &Bvh4Node,
+    ray: &PrecomputedRay,
+    t_interval: &Interval,
+) -> [f32; 4] {
+    let (near, far) = ray.near_far(node);
+    let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+
+    return std::array::from_fn(|slot| {
+        let mut t_enter = t_interval.min;
+        let mut t_exit = t_interval.max;
+        for axis in 0..3 {
+            let t_near = (near[axis][slot] - origin[axis]) * ray.inverse_direction[axis];
+            let t_far = (far[axis][slot] - origin[axis]) * ray.inverse_direction[axis];
+            t_enter = t_enter.max(t_near);
+            t_exit = t_exit.min(t_far);
+        }
+
+        return if t_enter <= t_exit {
+            t_enter
+        } else {
+            f32::INFINITY
+        };
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::irt::{Color, Lambertian, Material, Sphere, UnitVec3, Vec3};
+
+    fn spheres() -> Vec<Sphere> {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        return (0..300)
+            .map(|i| {
+                let center = Point::new(
+                    (i % 7) as f32 * 1.3,
+                    (i % 11) as f32 * 0.9,
+                    (i % 13) as f32 * 1.1,
+                );
+                Sphere::new(center, 0.2 + (i % 5) as f32 * 0.15, material.clone())
+            })
+            .collect();
+    }
+
+    #[test]
+    fn traversal_matches_brute_force() {
+        let brute_force = spheres();
+        let bvh4 = Bvh4::new(spheres());
+
+        for _ in 0..500 {
+            let ray = Ray::new(
+                Point::new(4., 5., -10.),
+                UnitVec3::random().as_vec3() + Vec3::new(0., 0., 1.),
+            );
+            let expected = brute_force.hit(&ray, &mut Interval::new(0.001, f32::INFINITY));
+            let actual = bvh4.hit(&ray, &mut Interval::new(0.001, f32::INFINITY));
+
+            match (expected, actual) {
+                (Some(expected), Some(actual)) => assert_eq!(expected.t, actual.t),
+                (None, None) => {}
+                (expected, actual) => panic!("Expected {expected:?}, got {actual:?}"),
+            }
+
+            let interval = Interval::new(0.001, f32::INFINITY);
+            assert_eq!(
+                bvh4.occluded(&ray, &interval),
+                brute_force.occluded(&ray, &interval)
+            );
+        }
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        let bvh4 = Bvh4::new(spheres());
+        let interval = Interval::new(0.001, f32::INFINITY);
+
+        for _ in 0..100 {
+            let ray = PrecomputedRay::new(&Ray::new(
+                Point::new(4., 5., -10.),
+                UnitVec3::random().as_vec3(),
+            ));
+            for node in &bvh4.nodes {
+                let scalar = intersect_children_scalar(node, &ray, &interval);
+                let simd = Bvh4::<Sphere>::intersect_children(node, &ray, &interval);
+                assert_eq!(scalar, simd);
+            }
+        }
+    }
+}
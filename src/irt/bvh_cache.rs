@@ -0,0 +1,336 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::irt::{Aabb, Bvh, BvhNode, Error, Material, Point, Result, Triangle};
+
+/// Identifies BVH cache files, followed by the version of the format.
+const MAGIC: &[u8; 6] = b"IRTBVH";
+const VERSION: u16 = 2;
+
+/// Hashes `bytes` with 64-bit FNV-1a.
+///
+/// Used to tie a BVH cache file to the mesh it was built from. Unlike
+/// [`std::hash::DefaultHasher`], the hash is stable across Rust versions.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    return bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    });
+}
+
+/// Caching of triangle mesh BVHs on disk.
+///
+/// The cache file is a little-endian binary file laid out as:
+///
+/// 1. The magic bytes `IRTBVH` and the format version as a `u16`
+/// 2. The hash of the source mesh as a `u64`
+/// 3. The number of nodes and triangles as `u64`s
+/// 4. The nodes, each as the min and max of its bounds (6 `f32`s),
+///    then `left_first` and `hittable_count` (2 `u32`s)
+/// 5. The triangles in BVH order, each as its 3 vertices (9 `f32`s), then whether
+///    it has texture coordinates (a `u32` of 0 or 1) and those of its vertices,
+///    or zeros (6 `f32`s)
+///
+/// Materials are not stored and must be provided when loading. Triangles with
+/// an opacity texture cannot be stored either.
+impl Bvh<Triangle> {
+    /// Writes the BVH to the cache file at `path`,
+    /// tagged with `source_hash`, the [`hash_bytes`] of the source mesh.
+    ///
+    /// Fails without writing anything if a triangle has an opacity texture.
+    pub fn save(&self, path: impl AsRef<Path>, source_hash: u64) -> Result<()> {
+        let path = path.as_ref();
+        if !self.hittables.iter().all(Triangle::is_opaque) {
+            let message = "triangles with an opacity texture cannot be cached";
+            return Err(Error::invalid(path, None, message));
+        }
+        return self
+            .write(path, source_hash)
+            .map_err(|error| Error::io(path, error));
+    }
+
+    fn write(&self, path: &Path, source_hash: u64) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&source_hash.to_le_bytes())?;
+        writer.write_all(&(self.nodes.len() as u64).to_le_bytes())?;
+        writer.write_all(&(self.hittables.len() as u64).to_le_bytes())?;
+
+        for node in &self.nodes {
+            write_point(&mut writer, node.bounds.min)?;
+            write_point(&mut writer, node.bounds.max)?;
+            writer.write_all(&(node.left_first as u32).to_le_bytes())?;
+            writer.write_all(&(node.hittable_count as u32).to_le_bytes())?;
+        }
+        for triangle in &self.hittables {
+            write_point(&mut writer, triangle.a)?;
+            write_point(&mut writer, triangle.b)?;
+            write_point(&mut writer, triangle.c)?;
+            let uvs = triangle.uvs();
+            writer.write_all(&(uvs.is_some() as u32).to_le_bytes())?;
+            for (u, v) in uvs.unwrap_or_default() {
+                writer.write_all(&u.to_le_bytes())?;
+                writer.write_all(&v.to_le_bytes())?;
+            }
+        }
+
+        return writer.flush();
+    }
+
+    /// Loads a BVH from the cache file at `path`, giving all triangles `material`.
+    ///
+    /// Returns `Ok(None)` if the cache is stale, i.e. if it was written
+    /// for a source mesh with a hash other than `source_hash`.
+    pub fn load(
+        path: impl AsRef<Path>,
+        source_hash: u64,
+        material: Arc<dyn Material>,
+    ) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|error| Error::io(path, error))?;
+        return Self::parse(&bytes, source_hash, material)
+            .map_err(|message| Error::invalid(path, None, message));
+    }
+
+    fn parse(
+        bytes: &[u8],
+        source_hash: u64,
+        material: Arc<dyn Material>,
+    ) -> Result<Option<Self>, &'static str> {
+        let mut reader = Reader::new(bytes);
+
+        if reader.bytes(MAGIC.len())? != MAGIC || reader.u16()? != VERSION {
+            return Err("not a BVH cache file of a supported version");
+        }
+        if reader.u64()? != source_hash {
+            return Ok(None);
+        }
+
+        let node_count = reader.u64()? as usize;
+        let triangle_count = reader.u64()? as usize;
+        if node_count == 0 {
+            return Err("BVH cache file has no nodes");
+        }
+        let expected_length = node_count
+            .checked_mul(32)
+            .zip(triangle_count.checked_mul(64))
+            .and_then(|(nodes_length, triangles_length)| nodes_length.checked_add(triangles_length))
+            .and_then(|length| length.checked_add(reader.position));
+        if expected_length != Some(bytes.len()) {
+            return Err("BVH cache file has an unexpected length");
+        }
+
+        let mut nodes = Vec::with_capacity(node_count);
+        for _ in 0..node_count {
+            let bounds = Aabb::new(reader.point()?, reader.point()?);
+            let left_first = reader.u32()? as usize;
+            let hittable_count = reader.u32()? as usize;
+            // Leaves must index existing triangles. Inner nodes must index a pair of
+            // existing nodes after themselves, as built, so that traversal terminates.
+            let valid = if hittable_count > 0 {
+                left_first + hittable_count <= triangle_count
+            } else {
+                left_first > nodes.len() && left_first + 1 < node_count
+            };
+            if !valid {
+                return Err("BVH cache file has a node out of range");
+            }
+            nodes.push(BvhNode {
+                bounds,
+                left_first,
+                hittable_count,
+            });
+        }
+
+        let mut hittables = Vec::with_capacity(triangle_count);
+        for _ in 0..triangle_count {
+            let triangle = Triangle::new(
+                reader.point()?,
+                reader.point()?,
+                reader.point()?,
+                material.clone(),
+            );
+            let has_uvs = reader.u32()? != 0;
+            let uvs = [
+                (reader.f32()?, reader.f32()?),
+                (reader.f32()?, reader.f32()?),
+                (reader.f32()?, reader.f32()?),
+            ];
+            hittables.push(match has_uvs {
+                true => triangle.with_uvs(uvs),
+                false => triangle,
+            });
+        }
+
+        let mut bvh = Self {
+            hittables,
+            nodes,
+            build_cost: 0.,
+        };
+        bvh.build_cost = bvh.sah2(0);
+
+        return Ok(Some(bvh));
+    }
+}
+
+fn write_point(writer: &mut impl Write, point: Point) -> io::Result<()> {
+    writer.write_all(&point.x.to_le_bytes())?;
+    writer.write_all(&point.y.to_le_bytes())?;
+    writer.write_all(&point.z.to_le_bytes())?;
+    return Ok(());
+}
+
+/// Reads little-endian values from a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        return Self { bytes, position: 0 };
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], &'static str> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + count)
+            .ok_or("BVH cache file is truncated")?;
+        self.position += count;
+        return Ok(bytes);
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], &'static str> {
+        return Ok(self.bytes(N)?.try_into().unwrap());
+    }
+
+    fn u16(&mut self) -> Result<u16, &'static str> {
+        return Ok(u16::from_le_bytes(self.array()?));
+    }
+
+    fn u32(&mut self) -> Result<u32, &'static str> {
+        return Ok(u32::from_le_bytes(self.array()?));
+    }
+
+    fn u64(&mut self) -> Result<u64, &'static str> {
+        return Ok(u64::from_le_bytes(self.array()?));
+    }
+
+    fn f32(&mut self) -> Result<f32, &'static str> {
+        return Ok(f32::from_le_bytes(self.array()?));
+    }
+
+    fn point(&mut self) -> Result<Point, &'static str> {
+        return Ok(Point::new(self.f32()?, self.f32()?, self.f32()?));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{Color, Hittable, Interval, Lambertian, Ray, Vec3};
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let triangles = (0..50)
+            .map(|i| {
+                let offset = Vec3::new(i as f32, (i % 3) as f32, 0.);
+                let triangle = Triangle::new(
+                    Point::new(0., 0., 0.) + offset,
+                    Point::new(1., 0., 0.) + offset,
+                    Point::new(0., 1., 0.) + offset,
+                    material.clone(),
+                );
+                match i % 2 {
+                    0 => triangle.with_uvs([(0., 0.), (0.5, 0.), (0., 0.5)]),
+                    _ => triangle,
+                }
+            })
+            .collect();
+        let bvh = Bvh::new(triangles);
+        let path = std::env::temp_dir().join("irt_bvh_cache_round_trip.bvh");
+
+        bvh.save(&path, 42).unwrap();
+        let loaded = Bvh::load(&path, 42, material.clone()).unwrap().unwrap();
+        let stale = Bvh::load(&path, 43, material.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let missing = Bvh::load(&path, 42, material);
+
+        assert!(stale.is_none());
+        assert!(matches!(missing, Err(Error::Io { .. })));
+        assert_eq!(loaded.nodes.len(), bvh.nodes.len());
+        for x in 0..50 {
+            let ray = Ray::new(
+                Point::new(x as f32 + 0.25, 0.25, -1.),
+                Vec3::new(0., 0., 1.),
+            );
+            let expected = bvh.hit(&ray, &mut Interval::new(0.001, f32::INFINITY));
+            let actual = loaded.hit(&ray, &mut Interval::new(0.001, f32::INFINITY));
+            assert_eq!(
+                expected.map(|hit| (hit.t, hit.u, hit.v)),
+                actual.map(|hit| (hit.t, hit.u, hit.v))
+            );
+        }
+    }
+
+    #[test]
+    fn save_rejects_masked_triangles() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let triangle = Triangle::new(
+            Point::new(0., 0., 0.),
+            Point::new(1., 0., 0.),
+            Point::new(0., 1., 0.),
+            material,
+        )
+        .with_opacity(Arc::new(Color::white()));
+        let path = std::env::temp_dir().join("irt_bvh_cache_masked.bvh");
+
+        let result = Bvh::new(vec![triangle]).save(&path, 42);
+        assert!(matches!(result, Err(Error::Invalid { .. })));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn load_rejects_malformed_files() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let header = |node_count: u64, triangle_count: u64| {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend(VERSION.to_le_bytes());
+            bytes.extend(7u64.to_le_bytes());
+            bytes.extend(node_count.to_le_bytes());
+            bytes.extend(triangle_count.to_le_bytes());
+            bytes
+        };
+        let node = |left_first: u32, hittable_count: u32| {
+            let mut bytes = [0f32; 6].map(f32::to_le_bytes).concat();
+            bytes.extend(left_first.to_le_bytes());
+            bytes.extend(hittable_count.to_le_bytes());
+            bytes
+        };
+
+        let empty = header(0, 0);
+        let overflowing = header(u64::MAX / 16, 1);
+        let leaf_out_of_range = [header(1, 0), node(0, 1)].concat();
+        let cycle = [header(3, 0), node(0, 0), node(0, 0), node(0, 0)].concat();
+
+        let path = std::env::temp_dir().join("irt_bvh_cache_malformed.bvh");
+        for bytes in [empty, overflowing, leaf_out_of_range, cycle] {
+            fs::write(&path, bytes).unwrap();
+            let result = Bvh::load(&path, 7, material.clone());
+            assert!(matches!(result, Err(Error::Invalid { .. })));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(hash_bytes(b""), 0xcbf29ce484222325);
+        assert_eq!(hash_bytes(b"a"), 0xaf63dc4c8601ec8c);
+    }
+}
@@ -1,7 +1,10 @@
 use indicatif::ParallelProgressIterator;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use crate::irt::{linear_to_gamma, Color, Degrees, Hittable, Interval, Point, Ray, UnitVec3, Vec3};
+use crate::irt::{
+    linear_to_gamma, Color, Degrees, Environment, GlobalMedium, Hit, Hittable, Interval, Light,
+    Point, Ray, UnitVec3, Vec3,
+};
 use std::fs::File;
 use std::io::Write;
 
@@ -33,6 +36,8 @@ pub struct Camera {
     pixel00_loc: Point,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
+    /// The angle covered by a pixel, by which camera ray cones widen.
+    pixel_spread: f32,
     samples_per_pixel: u32,
     /// The reciprocal of `samples_per_pixel`.
     ///
@@ -40,9 +45,15 @@ pub struct Camera {
     /// by the number of times the pixel is sampled.
     pixel_samples_scale: f32,
     max_depth: u32,
-    background_color: Color,
+    /// The light seen by rays that miss the scene.
+    environment: Box<dyn Environment>,
+    /// A medium filling the whole scene, if any.
+    medium: Option<GlobalMedium>,
+    /// Lights without geometry, sampled at each diffuse bounce.
+    lights: Vec<Box<dyn Light>>,
 }
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f32,
         vertical_fov: Degrees,
@@ -89,36 +100,159 @@ impl Camera {
             pixel00_loc,
             pixel_delta_u,
             pixel_delta_v,
+            pixel_spread: pixel_delta_v.length() / focal_length,
             samples_per_pixel,
             pixel_samples_scale,
             max_depth: 10,
-            background_color,
+            environment: Box::new(background_color),
+            medium: None,
+            lights: Vec::new(),
         };
     }
 
-    fn background_color(&self) -> Color {
-        return self.background_color;
+    /// Lights the scene with `environment` instead of the background color.
+    pub fn with_environment(self, environment: impl Environment + 'static) -> Self {
+        return Self {
+            environment: Box::new(environment),
+            ..self
+        };
+    }
+
+    /// Fills the whole scene with `medium`.
+    pub fn with_medium(self, medium: GlobalMedium) -> Self {
+        return Self {
+            medium: Some(medium),
+            ..self
+        };
+    }
+
+    /// Adds a light to the scene.
+    pub fn with_light(mut self, light: impl Light + 'static) -> Self {
+        self.lights.push(Box::new(light));
+        return self;
+    }
+
+    /// Returns the light of the environment seen by `ray`.
+    ///
+    /// `scatter_pdf` is the density with which the previous bounce sampled `ray`, if it
+    /// also sampled the environment explicitly. The sun disk is then left out, as it was
+    /// already sampled, and the rest of the environment is weighted by multiple importance
+    /// sampling against the explicit samples.
+    fn environment_radiance(&self, ray: &Ray, scatter_pdf: Option<f32>) -> Color {
+        let direction = ray.direction.normalize();
+        let radiance = self.environment.radiance(direction);
+
+        let Some(scatter_pdf) = scatter_pdf else {
+            let sun = self.environment.sun();
+            return radiance + sun.map_or(Color::black(), |sun| sun.radiance(direction));
+        };
+        return radiance * power_heuristic(scatter_pdf, self.environment.pdf(direction));
+    }
+
+    /// Returns the light arriving from `direction`, from a light `distance` away,
+    /// that is scattered at `hit` along `ray`, per unit of incoming light.
+    /// Black if the light is occluded.
+    ///
+    /// Returns `None` if the material of `hit` cannot be lit by sampling lights.
+    fn scattered_light(
+        &self,
+        ray: &Ray,
+        hit: &Hit,
+        world: &impl Hittable,
+        direction: UnitVec3,
+        distance: f32,
+    ) -> Option<Color> {
+        let scattering = hit.material.eval(ray, hit, direction)?;
+        if scattering.r + scattering.g + scattering.b <= 0. {
+            return Some(Color::black());
+        }
+
+        let shadow_ray = Ray::new(hit.point, direction.as_vec3()).with_mask(Ray::SHADOW);
+        if world.occluded(&shadow_ray, &Interval::new(0.001, distance - 0.001)) {
+            return Some(Color::black());
+        }
+        let transmittance = self
+            .medium
+            .as_ref()
+            .map_or(1., |medium| medium.transmittance(&shadow_ray, distance));
+
+        return Some(scattering * transmittance);
+    }
+
+    /// Samples the light scattered at `hit` along `ray` from the lights of the camera,
+    /// the sun, and the environment itself if it is importance sampled.
+    ///
+    /// Returns `None` if the material of `hit` cannot be lit by sampling lights.
+    /// Scattered rays must then see the whole environment instead.
+    fn direct_light(&self, ray: &Ray, hit: &Hit, world: &impl Hittable) -> Option<Color> {
+        let mut light = Color::black();
+
+        let sun = self.environment.sun().map(|sun| sun as &dyn Light);
+        for source in self.lights.iter().map(Box::as_ref).chain(sun) {
+            if let Some(sample) = source.sample(hit.point) {
+                let scattering =
+                    self.scattered_light(ray, hit, world, sample.direction, sample.distance)?;
+                light += scattering * sample.irradiance;
+            }
+        }
+
+        if let Some((direction, radiance, pdf)) = self.environment.sample() {
+            let scattering = self.scattered_light(ray, hit, world, direction, f32::INFINITY)?;
+            let weight = power_heuristic(pdf, hit.material.pdf(ray, hit, direction));
+            light += scattering * radiance * (weight / pdf);
+        }
+
+        return Some(light);
     }
 
-    fn ray_color(&self, ray: &Ray, depth: u32, world: &impl Hittable) -> Color {
+    /// Returns the light arriving along `ray`, after at most `depth` bounces.
+    /// See [`Camera::environment_radiance`] for `scatter_pdf`.
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        depth: u32,
+        world: &impl Hittable,
+        scatter_pdf: Option<f32>,
+    ) -> Color {
         if depth == 0 {
             return Color::black();
         }
 
-        let potential_hit = world.hit(ray, &mut Interval::new(0.001, f32::INFINITY));
-        let Some(hit) = potential_hit else {
-            return self.background_color();
+        let mut potential_hit = world.hit(ray, &mut Interval::new(0.001, f32::INFINITY));
+        if let Some(medium) = &self.medium {
+            let t_max = potential_hit.as_ref().map_or(f32::INFINITY, |hit| hit.t);
+            if let Some(medium_hit) = medium.hit(ray, t_max) {
+                potential_hit = Some(medium_hit);
+            }
+        }
+        let Some(mut hit) = potential_hit else {
+            return self.environment_radiance(ray, scatter_pdf);
         };
+        // Instanced geometry measures the cone along the ray in object space
+        hit.cone_width = ray.cone_width_at(hit.t);
 
-        let color_from_emission = hit.material.emitted(hit.u, hit.v, hit.point);
+        let color_from_emission = hit.material.emitted(ray, &hit);
+        let color_from_lights = self.direct_light(ray, &hit, world);
 
-        let potential_scatter = hit.material.scatter(ray, &hit);
-        let Some((scattered, attenuation)) = potential_scatter else {
-            return color_from_emission;
+        let potential_scatter = hit.material.scatter_specular(ray, &hit);
+        let Some((scattered, attenuation, specular)) = potential_scatter else {
+            return color_from_emission + color_from_lights.unwrap_or(Color::black());
         };
-        let color_from_scatter = attenuation * self.ray_color(&scattered, depth - 1, world);
+        // The cone keeps widening from the footprint of the hit, ignoring the curvature of
+        // the surface, which makes it a lower bound on the footprint of diffuse bounces
+        let scattered = scattered
+            .with_mask(Ray::REFLECTION)
+            .with_cone(hit.cone_width, ray.cone_spread);
+        // Specular rays see the lights in full, as they were not sampled for them
+        let scatter_pdf = color_from_lights
+            .filter(|_| !specular)
+            .map(|_| hit.material.pdf(ray, &hit, scattered.direction.normalize()));
+        let color_from_scatter =
+            attenuation * self.ray_color(&scattered, depth - 1, world, scatter_pdf);
 
-        return color_from_emission + color_from_scatter;
+        return color_from_emission
+            + color_from_lights.unwrap_or(Color::black())
+            + color_from_scatter;
     }
 
     /// Returns the `x` and `y` coordinates of a random point
@@ -135,7 +269,9 @@ impl Camera {
             + ((x as f32 + offset_x) * self.pixel_delta_u)
             + ((y as f32 + offset_y) * self.pixel_delta_v);
 
-        return Ray::new(self.center, pixel_sample - self.center);
+        return Ray::new(self.center, pixel_sample - self.center)
+            .with_mask(Ray::CAMERA)
+            .with_cone(0., self.pixel_spread);
     }
 
     fn coords_from_index(&self, index: u32) -> (u32, u32) {
@@ -150,7 +286,7 @@ impl Camera {
         let mut color = Color::black();
         for _ in 0..self.samples_per_pixel {
             let ray = self.get_ray((x, y));
-            color += self.ray_color(&ray, self.max_depth, world);
+            color += self.ray_color(&ray, self.max_depth, world, None);
         }
 
         return color * self.pixel_samples_scale;
@@ -188,3 +324,12 @@ impl Camera {
         println!("Print finished.");
     }
 }
+
+/// Weighs a sample taken with density `pdf` against another strategy that could have
+/// taken it with density `other_pdf`, for multiple importance sampling.
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    if other_pdf <= 0. {
+        return 1.;
+    }
+    return pdf * pdf / (pdf * pdf + other_pdf * other_pdf);
+}
@@ -1,5 +1,6 @@
 use std::ops::{Add, AddAssign, Mul, MulAssign};
 
+use image::Rgb32FImage;
 use rand::prelude::*;
 
 use crate::irt::Interval;
@@ -27,6 +28,11 @@ impl Color {
         return Self::new(0., 1., 1.);
     }
 
+    /// The relative luminance of the color, with the weights of linear sRGB.
+    pub fn luminance(&self) -> f32 {
+        return 0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b;
+    }
+
     pub fn random() -> Self {
         let mut rng = thread_rng();
         return Self::new(rng.gen(), rng.gen(), rng.gen());
@@ -86,3 +92,68 @@ pub fn linear_to_gamma(linear_component: f32) -> f32 {
     }
     return 0.;
 }
+
+/// The encoding of the colors of a texture, which are converted on load to the linear
+/// sRGB primaries the renderer works in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Colors encoded with the sRGB transfer function, as in most JPEG and PNG images.
+    Srgb,
+    /// Linear colors with sRGB primaries, as in HDR images.
+    Linear,
+    /// Data which isn't a color, such as normal, roughness or opacity maps. Left untouched.
+    Raw,
+}
+impl ColorSpace {
+    /// Returns `color`, encoded in this color space, in linear sRGB.
+    pub fn decode(self, color: Color) -> Color {
+        return match self {
+            ColorSpace::Srgb => Color::new(
+                srgb_to_linear(color.r),
+                srgb_to_linear(color.g),
+                srgb_to_linear(color.b),
+            ),
+            ColorSpace::Linear | ColorSpace::Raw => color,
+        };
+    }
+
+    /// Converts the pixels of `image`, encoded in this color space, to linear sRGB.
+    pub fn decode_image(self, image: &mut Rgb32FImage) {
+        for pixel in image.pixels_mut() {
+            let [r, g, b] = pixel.0;
+            let color = self.decode(Color::new(r, g, b));
+            pixel.0 = [color.r, color.g, color.b];
+        }
+    }
+}
+
+/// Applies the sRGB electro-optical transfer function to a component in 0..1.
+fn srgb_to_linear(encoded_component: f32) -> f32 {
+    if encoded_component <= 0.04045 {
+        return encoded_component / 12.92;
+    }
+    return ((encoded_component + 0.055) / 1.055).powf(2.4);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::approx_equals;
+
+    #[test]
+    fn srgb_decodes_to_darker_linear_values() {
+        let decoded = ColorSpace::Srgb.decode(Color::new(0., 0.5, 1.));
+        assert_eq!(decoded.r, 0.);
+        assert!(approx_equals(decoded.g, 0.21404));
+        assert!(approx_equals(decoded.b, 1.));
+
+        // The linear segment near black
+        assert!(approx_equals(
+            ColorSpace::Srgb.decode(Color::new(0.02, 0., 0.)).r,
+            0.02 / 12.92
+        ));
+
+        let data = ColorSpace::Raw.decode(Color::new(0.5, 0.5, 1.));
+        assert_eq!((data.r, data.g, data.b), (0.5, 0.5, 1.));
+    }
+}
@@ -0,0 +1,314 @@
+use std::sync::Arc;
+
+use rand::random;
+
+use crate::irt::{dielectric_fresnel, Color, Hit, Material, Ray, Texture, UnitVec3};
+
+/// Blends two materials, such as clean and dirty metal, by a factor given by a texture:
+/// 0 gives the first material, 1 the second. Scalar factors read the red channel.
+///
+/// Light sources are sampled for the parts of the materials which support it.
+#[derive(Debug)]
+pub struct MixMaterial {
+    first: Arc<dyn Material>,
+    second: Arc<dyn Material>,
+    factor: Box<dyn Texture>,
+}
+impl MixMaterial {
+    pub fn new(
+        first: Arc<dyn Material>,
+        second: Arc<dyn Material>,
+        factor: impl Texture + 'static,
+    ) -> Self {
+        return Self {
+            first,
+            second,
+            factor: Box::new(factor),
+        };
+    }
+
+    fn factor(&self, hit: &Hit) -> f32 {
+        return self.factor.value_at(hit).r.clamp(0., 1.);
+    }
+}
+impl Material for MixMaterial {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
+        return self
+            .scatter_specular(ray_in, hit)
+            .map(|(scattered, attenuation, _)| (scattered, attenuation));
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit: &Hit) -> Color {
+        let factor = self.factor(hit);
+        return self.first.emitted(ray_in, hit) * (1. - factor)
+            + self.second.emitted(ray_in, hit) * factor;
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> Option<Color> {
+        let factor = self.factor(hit);
+        let first = self.first.eval(ray_in, hit, direction);
+        let second = self.second.eval(ray_in, hit, direction);
+        if first.is_none() && second.is_none() {
+            return None;
+        }
+        return Some(
+            first.unwrap_or(Color::black()) * (1. - factor)
+                + second.unwrap_or(Color::black()) * factor,
+        );
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> f32 {
+        let factor = self.factor(hit);
+        return self.first.pdf(ray_in, hit, direction) * (1. - factor)
+            + self.second.pdf(ray_in, hit, direction) * factor;
+    }
+
+    fn scatter_specular(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color, bool)> {
+        // Choosing one of the materials with the probability of its weight leaves
+        // its scattered light unweighted
+        return match self.factor(hit) > random() {
+            true => scatter_component(self.second.as_ref(), ray_in, hit),
+            false => scatter_component(self.first.as_ref(), ray_in, hit),
+        };
+    }
+}
+
+/// A thin dielectric coating, such as varnish or lacquer, over a base material.
+///
+/// The coating reflects light by the Fresnel reflectance of its refractive index, and the
+/// base receives what the coating transmits, on its way in and out. The coating's own
+/// material gives the shape of its reflection: typically a white [`Conductor`] of the
+/// coating's roughness, as the layer applies the Fresnel term itself.
+///
+/// Light sources are sampled for the parts of the materials which support it.
+///
+/// [`Conductor`]: crate::irt::Conductor
+#[derive(Debug)]
+pub struct LayeredMaterial {
+    coating: Arc<dyn Material>,
+    base: Arc<dyn Material>,
+    refraction_index: f32,
+}
+impl LayeredMaterial {
+    pub fn new(coating: Arc<dyn Material>, base: Arc<dyn Material>, refraction_index: f32) -> Self {
+        return Self {
+            coating,
+            base,
+            refraction_index,
+        };
+    }
+
+    /// The reflectance of the coating for light leaving or arriving along `direction`.
+    fn fresnel(&self, hit: &Hit, direction: UnitVec3) -> f32 {
+        let cos_theta = hit.normal.as_vec3().dot(direction.as_vec3()).abs();
+        return dielectric_fresnel(cos_theta, self.refraction_index);
+    }
+}
+impl Material for LayeredMaterial {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
+        return self
+            .scatter_specular(ray_in, hit)
+            .map(|(scattered, attenuation, _)| (scattered, attenuation));
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit: &Hit) -> Color {
+        let transmitted = 1. - self.fresnel(hit, ray_in.direction.normalize());
+        return self.coating.emitted(ray_in, hit) + self.base.emitted(ray_in, hit) * transmitted;
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> Option<Color> {
+        let reflected = self.fresnel(hit, ray_in.direction.normalize());
+        let transmitted = (1. - reflected) * (1. - self.fresnel(hit, direction));
+        let coating = self.coating.eval(ray_in, hit, direction);
+        let base = self.base.eval(ray_in, hit, direction);
+        if coating.is_none() && base.is_none() {
+            return None;
+        }
+        return Some(
+            coating.unwrap_or(Color::black()) * reflected
+                + base.unwrap_or(Color::black()) * transmitted,
+        );
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> f32 {
+        let reflected = self.fresnel(hit, ray_in.direction.normalize());
+        return self.coating.pdf(ray_in, hit, direction) * reflected
+            + self.base.pdf(ray_in, hit, direction) * (1. - reflected);
+    }
+
+    fn scatter_specular(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color, bool)> {
+        if self.fresnel(hit, ray_in.direction.normalize()) > random() {
+            return scatter_component(self.coating.as_ref(), ray_in, hit);
+        }
+
+        let (scattered, attenuation, specular) =
+            scatter_component(self.base.as_ref(), ray_in, hit)?;
+        let transmitted = 1. - self.fresnel(hit, scattered.direction.normalize());
+        return Some((scattered, attenuation * transmitted, specular));
+    }
+}
+
+/// A material which differs between the front and the back of a surface,
+/// such as a label printed on one side of a sheet.
+#[derive(Debug)]
+pub struct TwoSidedMaterial {
+    front: Arc<dyn Material>,
+    back: Arc<dyn Material>,
+}
+impl TwoSidedMaterial {
+    pub fn new(front: Arc<dyn Material>, back: Arc<dyn Material>) -> Self {
+        return Self { front, back };
+    }
+
+    fn side(&self, hit: &Hit) -> &dyn Material {
+        return match hit.front_face {
+            true => self.front.as_ref(),
+            false => self.back.as_ref(),
+        };
+    }
+}
+impl Material for TwoSidedMaterial {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
+        return self.side(hit).scatter(ray_in, hit);
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit: &Hit) -> Color {
+        return self.side(hit).emitted(ray_in, hit);
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> Option<Color> {
+        return self.side(hit).eval(ray_in, hit, direction);
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> f32 {
+        return self.side(hit).pdf(ray_in, hit, direction);
+    }
+
+    fn scatter_specular(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color, bool)> {
+        return self.side(hit).scatter_specular(ray_in, hit);
+    }
+}
+
+/// Scatters off one material of a composite. The ray is specular if the material
+/// cannot be evaluated at all, such as glass, or scattered it with a part that cannot.
+fn scatter_component(
+    material: &dyn Material,
+    ray_in: &Ray,
+    hit: &Hit,
+) -> Option<(Ray, Color, bool)> {
+    let (scattered, attenuation, specular) = material.scatter_specular(ray_in, hit)?;
+    let direction = scattered.direction.normalize();
+    let specular = specular || material.eval(ray_in, hit, direction).is_none();
+    return Some((scattered, attenuation, specular));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{approx_equals, Conductor, Dielectric, Lambertian, Point, Vec3};
+    use std::f32::consts::PI;
+
+    fn hit_from_above<'a>(ray: &Ray, material: &'a dyn Material) -> Hit<'a> {
+        let normal = Vec3::new(0., 1., 0.).normalize();
+        return Hit::new(ray, Point::new(0., 0., 0.), normal, 1., material, 0., 0.);
+    }
+
+    #[test]
+    fn mix_blends_evaluations() {
+        let white = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let black = Arc::new(Lambertian::new(Box::new(Color::black())));
+        let mix = MixMaterial::new(white.clone(), black, 0.25);
+
+        let ray = Ray::new(Point::new(0., 1., 0.), Vec3::new(0., -1., 0.));
+        let hit = hit_from_above(&ray, &mix);
+        let up = Vec3::new(0., 1., 0.).normalize();
+        let eval = mix.eval(&ray, &hit, up).unwrap();
+        assert!(approx_equals(
+            eval.r,
+            0.75 * white.eval(&ray, &hit, up).unwrap().r
+        ));
+
+        // Specular components are left out of evaluations, unless there is nothing else
+        let glass = Arc::new(Dielectric::new(1.5));
+        let half_glass = MixMaterial::new(white.clone(), glass.clone(), 0.5);
+        let eval = half_glass.eval(&ray, &hit, up).unwrap();
+        assert!(approx_equals(
+            eval.r,
+            0.5 * white.eval(&ray, &hit, up).unwrap().r
+        ));
+        let all_glass = MixMaterial::new(glass.clone(), glass, 0.5);
+        assert!(all_glass.eval(&ray, &hit, up).is_none());
+    }
+
+    #[test]
+    fn coating_reflects_by_fresnel() {
+        let mirror = Arc::new(Conductor::from_reflectance(Color::white(), 0.));
+        let black = Arc::new(Lambertian::new(Box::new(Color::black())));
+        let varnish = LayeredMaterial::new(mirror, black, 1.5);
+
+        let ray = Ray::new(Point::new(0., 1., 0.), Vec3::new(0., -1., 0.));
+        let hit = hit_from_above(&ray, &varnish);
+        let samples = 100_000;
+        let reflected = (0..samples)
+            .filter_map(|_| varnish.scatter(&ray, &hit))
+            .map(|(_, attenuation)| attenuation.r)
+            .sum::<f32>()
+            / samples as f32;
+        assert!((reflected - 0.04).abs() < 0.005);
+    }
+
+    #[test]
+    fn smooth_coating_over_diffuse_base_is_evaluated() {
+        let mirror = Arc::new(Conductor::from_reflectance(Color::white(), 0.));
+        let white = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let varnish = LayeredMaterial::new(mirror, white, 1.5);
+
+        let ray = Ray::new(Point::new(0., 1., 0.), Vec3::new(0., -1., 0.));
+        let hit = hit_from_above(&ray, &varnish);
+        let up = Vec3::new(0., 1., 0.).normalize();
+        let eval = varnish.eval(&ray, &hit, up).unwrap();
+        assert!(approx_equals(eval.r, 0.96 * 0.96 / PI));
+
+        // Only rays reflected by the coating are specular
+        let samples = (0..10_000).filter_map(|_| varnish.scatter_specular(&ray, &hit));
+        let (specular, diffuse): (Vec<_>, Vec<_>) = samples.partition(|(_, _, specular)| *specular);
+        assert!(!specular.is_empty() && !diffuse.is_empty());
+        for (scattered, _, _) in specular {
+            assert!(approx_equals(
+                scattered.direction.normalize().as_vec3().y,
+                1.
+            ));
+        }
+    }
+
+    #[test]
+    fn two_sided_material_picks_the_hit_side() {
+        let white = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let two_sided = TwoSidedMaterial::new(white, Arc::new(Dielectric::new(1.5)));
+
+        let normal = Vec3::new(0., 1., 0.).normalize();
+        let above = Ray::new(Point::new(0., 1., 0.), Vec3::new(0., -1., 0.));
+        let hit = Hit::new(
+            &above,
+            Point::new(0., 0., 0.),
+            normal,
+            1.,
+            &two_sided,
+            0.,
+            0.,
+        );
+        assert!(two_sided.eval(&above, &hit, normal).is_some());
+
+        let below = Ray::new(Point::new(0., -1., 0.), Vec3::new(0., 1., 0.));
+        let hit = Hit::new(
+            &below,
+            Point::new(0., 0., 0.),
+            normal,
+            1.,
+            &two_sided,
+            0.,
+            0.,
+        );
+        assert!(two_sided.eval(&below, &hit, -normal).is_none());
+    }
+}
@@ -0,0 +1,329 @@
+use std::fs;
+use std::path::Path;
+
+use crate::irt::{lerp, Aabb, Error, Perlin, Point, Result};
+
+/// A density that varies in space, filling a [`HeterogeneousMedium`](crate::irt::HeterogeneousMedium).
+pub trait DensityField: Send + Sync {
+    fn density(&self, point: Point) -> f32;
+
+    /// An upper bound of the density everywhere in the field,
+    /// used as the majorant when tracking rays through the medium.
+    fn max_density(&self) -> f32;
+}
+
+/// A density defined by Perlin noise, for clouds and smoke.
+/// Regions where the noise is negative are empty.
+#[derive(Debug)]
+pub struct NoiseDensity {
+    noise: Perlin,
+    scale: f32,
+    density: f32,
+}
+impl NoiseDensity {
+    /// `scale` is the frequency of the noise, and `density` is the densest the field gets.
+    pub fn new(scale: f32, density: f32) -> Self {
+        return Self {
+            noise: Perlin::new(),
+            scale,
+            density,
+        };
+    }
+}
+impl DensityField for NoiseDensity {
+    fn density(&self, point: Point) -> f32 {
+        return self.density * self.noise.noise(self.scale * point).clamp(0., 1.);
+    }
+
+    fn max_density(&self) -> f32 {
+        return self.density;
+    }
+}
+
+/// Storage of the voxels of a [`VoxelGrid`].
+#[derive(Debug)]
+enum Voxels {
+    /// All voxels, x varying fastest, then y, then z.
+    Dense(Vec<f32>),
+    /// Cubic bricks of [`VoxelGrid::BRICK_SIZE`] voxels per side, laid out like dense voxels.
+    /// Bricks without any density are not stored.
+    Sparse {
+        bricks: Vec<Option<Box<[f32]>>>,
+        brick_resolution: [usize; 3],
+    },
+}
+
+/// A density defined by a grid of voxels stretched over a box,
+/// interpolated trilinearly between voxel centers.
+#[derive(Debug)]
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    voxels: Voxels,
+    bounds: Aabb,
+    max_density: f32,
+}
+impl VoxelGrid {
+    const BRICK_SIZE: usize = 8;
+    /// The most voxels a grid may have, dense or sparse.
+    const MAX_VOXELS: usize = 1 << 30;
+
+    /// Creates a grid of `resolution` voxels filling `bounds`.
+    /// `densities` holds every voxel, x varying fastest, then y, then z.
+    pub fn dense(resolution: [usize; 3], densities: Vec<f32>, bounds: Aabb) -> Self {
+        assert!(
+            !resolution.contains(&0),
+            "voxel grids need voxels along every axis"
+        );
+        let voxel_count = Self::voxel_count(resolution).expect("voxel grid is too large");
+        assert_eq!(densities.len(), voxel_count);
+
+        return Self {
+            resolution,
+            max_density: densities.iter().copied().fold(0., f32::max),
+            voxels: Voxels::Dense(densities),
+            bounds,
+        };
+    }
+
+    /// Creates a grid of `resolution` voxels filling `bounds`, storing only the
+    /// `(voxel, density)` pairs given. All other voxels are empty.
+    pub fn sparse(
+        resolution: [usize; 3],
+        densities: impl IntoIterator<Item = ([usize; 3], f32)>,
+        bounds: Aabb,
+    ) -> Self {
+        assert!(
+            !resolution.contains(&0),
+            "voxel grids need voxels along every axis"
+        );
+        assert!(
+            Self::voxel_count(resolution).is_some(),
+            "voxel grid is too large"
+        );
+        let brick_resolution = resolution.map(|count| count.div_ceil(Self::BRICK_SIZE));
+        let mut bricks: Vec<Option<Box<[f32]>>> = vec![None; brick_resolution.iter().product()];
+        let mut max_density: f32 = 0.;
+
+        for ([x, y, z], density) in densities {
+            assert!(x < resolution[0] && y < resolution[1] && z < resolution[2]);
+            let (brick, voxel) = Self::brick_index(brick_resolution, x, y, z);
+            let brick = bricks[brick]
+                .get_or_insert_with(|| vec![0.; Self::BRICK_SIZE.pow(3)].into_boxed_slice());
+            brick[voxel] = density;
+            max_density = max_density.max(density);
+        }
+
+        return Self {
+            resolution,
+            voxels: Voxels::Sparse {
+                bricks,
+                brick_resolution,
+            },
+            bounds,
+            max_density,
+        };
+    }
+
+    /// Loads a grid from a text file, stretched over `bounds`.
+    ///
+    /// The file starts with `dense` or `sparse` and the resolution of the grid along x, y and z.
+    /// Dense grids follow with the density of every voxel, x varying fastest, then y, then z.
+    /// Sparse grids follow with the `x y z density` of each voxel that is not empty.
+    /// Either way, a grid may have at most 2^30 voxels.
+    pub fn load(path: impl AsRef<Path>, bounds: Aabb) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        return Self::parse(&text, bounds).map_err(|message| Error::invalid(path, None, message));
+    }
+
+    fn parse(text: &str, bounds: Aabb) -> Result<Self, &'static str> {
+        let mut tokens = text.split_whitespace();
+
+        let kind = tokens.next().ok_or("voxel file is empty")?;
+        let mut resolution = [0; 3];
+        for count in &mut resolution {
+            *count = parse(tokens.next())?;
+        }
+        if resolution.contains(&0) {
+            return Err("voxel file has no voxels along an axis");
+        }
+        let voxel_count = Self::voxel_count(resolution).ok_or("voxel file is too large")?;
+
+        match kind {
+            "dense" => {
+                let densities = tokens
+                    .map(|token| parse(Some(token)))
+                    .collect::<Result<Vec<f32>, _>>()?;
+                if densities.len() != voxel_count {
+                    return Err("voxel file has an unexpected number of voxels");
+                }
+                return Ok(Self::dense(resolution, densities, bounds));
+            }
+            "sparse" => {
+                let mut densities = Vec::new();
+                while let Some(x) = tokens.next() {
+                    let voxel = [
+                        parse(Some(x))?,
+                        parse(tokens.next())?,
+                        parse(tokens.next())?,
+                    ];
+                    if voxel.iter().zip(resolution).any(|(&i, count)| i >= count) {
+                        return Err("voxel file has a voxel outside the grid");
+                    }
+                    densities.push((voxel, parse(tokens.next())?));
+                }
+                return Ok(Self::sparse(resolution, densities, bounds));
+            }
+            _ => return Err("voxel file is neither dense nor sparse"),
+        }
+    }
+
+    /// Returns the number of voxels in a grid of `resolution`,
+    /// or `None` if it exceeds [`VoxelGrid::MAX_VOXELS`].
+    fn voxel_count(resolution: [usize; 3]) -> Option<usize> {
+        return resolution
+            .iter()
+            .try_fold(1_usize, |count, &axis| count.checked_mul(axis))
+            .filter(|&count| count <= Self::MAX_VOXELS);
+    }
+
+    /// Returns the index of the brick holding a voxel, and the index of the voxel in the brick.
+    fn brick_index(brick_resolution: [usize; 3], x: usize, y: usize, z: usize) -> (usize, usize) {
+        let size = Self::BRICK_SIZE;
+        let brick = (x / size)
+            + (y / size) * brick_resolution[0]
+            + (z / size) * brick_resolution[0] * brick_resolution[1];
+        let voxel = (x % size) + (y % size) * size + (z % size) * size * size;
+        return (brick, voxel);
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        match &self.voxels {
+            Voxels::Dense(densities) => {
+                return densities[x + (y + z * self.resolution[1]) * self.resolution[0]];
+            }
+            Voxels::Sparse {
+                bricks,
+                brick_resolution,
+            } => {
+                let (brick, voxel) = Self::brick_index(*brick_resolution, x, y, z);
+                return bricks[brick].as_ref().map_or(0., |brick| brick[voxel]);
+            }
+        }
+    }
+}
+impl DensityField for VoxelGrid {
+    fn density(&self, point: Point) -> f32 {
+        let size = self.bounds.max - self.bounds.min;
+        let relative = point - self.bounds.min;
+        let relative = [
+            relative.x / size.x,
+            relative.y / size.y,
+            relative.z / size.z,
+        ];
+        if relative.iter().any(|t| !(0. ..=1.).contains(t)) {
+            return 0.;
+        }
+
+        // Voxel coordinates relative to the voxel centers, split into the
+        // lower voxel and the interpolation weight towards the upper voxel
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut weights = [0.; 3];
+        for axis in 0..3 {
+            let count = self.resolution[axis];
+            let coordinate = (relative[axis] * count as f32 - 0.5).clamp(0., (count - 1) as f32);
+            lower[axis] = coordinate as usize;
+            upper[axis] = (lower[axis] + 1).min(count - 1);
+            weights[axis] = coordinate - lower[axis] as f32;
+        }
+
+        let [x0, y0, z0] = lower;
+        let [x1, y1, z1] = upper;
+        let [u, v, w] = weights;
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), u);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), u);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), u);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), u);
+
+        return lerp(lerp(c00, c10, v), lerp(c01, c11, v), w);
+    }
+
+    fn max_density(&self) -> f32 {
+        return self.max_density;
+    }
+}
+
+fn parse<T: std::str::FromStr>(token: Option<&str>) -> Result<T, &'static str> {
+    return token
+        .and_then(|token| token.parse().ok())
+        .ok_or("voxel file is truncated or has an invalid number");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::approx_equals;
+
+    #[test]
+    fn dense_and_sparse_files_agree() {
+        let bounds = Aabb::new(Point::new(0., 0., 0.), Point::new(10., 20., 10.));
+        let dir = std::env::temp_dir();
+        let dense_path = dir.join("irt_voxels_dense.txt");
+        let sparse_path = dir.join("irt_voxels_sparse.txt");
+
+        // A 10x10x10 grid, empty but for a single voxel of each layer along the diagonal
+        let mut dense = String::from("dense 10 10 10\n");
+        let mut sparse = String::from("sparse 10 10 10\n");
+        for z in 0..10 {
+            for y in 0..10 {
+                for x in 0..10 {
+                    let density = if x == y && y == z { z as f32 } else { 0. };
+                    dense.push_str(&format!("{density} "));
+                    if density > 0. {
+                        sparse.push_str(&format!("{x} {y} {z} {density}\n"));
+                    }
+                }
+            }
+        }
+        fs::write(&dense_path, dense).unwrap();
+        fs::write(&sparse_path, sparse).unwrap();
+        let dense = VoxelGrid::load(&dense_path, bounds).unwrap();
+        let sparse = VoxelGrid::load(&sparse_path, bounds).unwrap();
+        fs::remove_file(dense_path).unwrap();
+        fs::remove_file(sparse_path).unwrap();
+
+        assert_eq!(dense.max_density(), 9.);
+        assert_eq!(sparse.max_density(), 9.);
+        for i in 0..=40 {
+            let t = i as f32 / 40.;
+            let point = Point::new(10. * t, 20. * t, 10. * t);
+            assert!(approx_equals(dense.density(point), sparse.density(point)));
+        }
+        // At the center of voxel (3, 3, 3), and halfway to the empty voxel (4, 3, 3)
+        assert!(approx_equals(dense.density(Point::new(3.5, 7., 3.5)), 3.));
+        assert!(approx_equals(dense.density(Point::new(4., 7., 3.5)), 1.5));
+        assert_eq!(dense.density(Point::new(-1., 0., 0.)), 0.);
+    }
+
+    #[test]
+    fn parse_rejects_empty_axes() {
+        let bounds = Aabb::new(Point::new(0., 0., 0.), Point::new(1., 1., 1.));
+        assert!(VoxelGrid::parse("dense 2 0 2\n", bounds).is_err());
+        assert!(VoxelGrid::parse("sparse 0 4 4\n", bounds).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_oversized_grids() {
+        let bounds = Aabb::new(Point::new(0., 0., 0.), Point::new(1., 1., 1.));
+        let overflowing = "sparse 4294967296 4294967296 4294967296\n";
+        assert_eq!(
+            VoxelGrid::parse(overflowing, bounds).unwrap_err(),
+            "voxel file is too large"
+        );
+        assert_eq!(
+            VoxelGrid::parse("sparse 4096 4096 4096\n", bounds).unwrap_err(),
+            "voxel file is too large"
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use crate::irt::{Color, DirectionalLight, UnitVec3};
+
+/// The light arriving from infinitely far away, seen by rays that miss the scene.
+pub trait Environment: Send + Sync {
+    /// Returns the radiance arriving from `direction`, excluding the sun disk.
+    fn radiance(&self, direction: UnitVec3) -> Color;
+
+    /// Returns the sun of the environment, if any.
+    ///
+    /// The sun is sampled explicitly like other lights. Its disk is only seen by rays
+    /// that come from a camera or a specular bounce, which cannot sample it.
+    fn sun(&self) -> Option<&DirectionalLight> {
+        return None;
+    }
+
+    /// Samples a direction of the environment for explicit light sampling,
+    /// returning it with its radiance and its probability density over solid angle.
+    ///
+    /// Returns `None` for environments that are not importance sampled.
+    fn sample(&self) -> Option<(UnitVec3, Color, f32)> {
+        return None;
+    }
+
+    /// Returns the probability density over solid angle of [`Environment::sample`]
+    /// returning `direction`.
+    fn pdf(&self, _direction: UnitVec3) -> f32 {
+        return 0.;
+    }
+}
+
+/// A uniform background.
+impl Environment for Color {
+    fn radiance(&self, _direction: UnitVec3) -> Color {
+        return *self;
+    }
+}
@@ -0,0 +1,262 @@
+use std::f32::consts::PI;
+
+use image::Rgb32FImage;
+use rand::random;
+
+use crate::irt::{Color, ColorSpace, Degrees, Environment, Error, Result, UnitVec3, Vec3};
+
+/// An environment lit by an equirectangular high dynamic range image,
+/// such as a captured `.hdr` or `.exr` HDRI.
+///
+/// The image is importance sampled by luminance, so small bright regions
+/// such as the sun or studio lights are found by shadow rays.
+#[derive(Debug)]
+pub struct EnvironmentMap {
+    image: Rgb32FImage,
+    /// The rotation of the map around the vertical axis, in radians.
+    rotation: f32,
+    intensity: f32,
+    /// The cumulative distribution of the rows, ending at 1.
+    row_cdf: Vec<f32>,
+    /// The cumulative distribution of the pixels of each row, ending at 1 in each row.
+    column_cdfs: Vec<f32>,
+    /// The probability of sampling each pixel.
+    pixel_probabilities: Vec<f32>,
+}
+impl EnvironmentMap {
+    /// Loads an image whose pixels are encoded in `color_space`,
+    /// typically [`ColorSpace::Linear`] for HDR images.
+    pub fn new(image_filename: &str, color_space: ColorSpace) -> Result<Self> {
+        let mut image = image::open(image_filename)
+            .map_err(|error| Error::image(image_filename, error))?
+            .to_rgb32f();
+        color_space.decode_image(&mut image);
+        return Self::from_image(image)
+            .map_err(|message| Error::invalid(image_filename, None, message));
+    }
+
+    fn from_image(image: Rgb32FImage) -> Result<Self, &'static str> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        if width == 0 || height == 0 {
+            return Err("environment map image is empty");
+        }
+
+        // Pixels are weighted by the solid angle they cover, which shrinks towards the poles
+        let weights: Vec<f32> = image
+            .enumerate_pixels()
+            .map(|(_, y, pixel)| {
+                let theta = PI * (y as f32 + 0.5) / height as f32;
+                let [r, g, b] = pixel.0;
+                Color::new(r, g, b).luminance().max(0.) * theta.sin()
+            })
+            .collect();
+        let total: f32 = weights.iter().sum();
+        let pixel_probabilities: Vec<f32> = if total > 0. {
+            weights.iter().map(|weight| weight / total).collect()
+        } else {
+            vec![1. / (width * height) as f32; width * height]
+        };
+
+        let mut row_cdf = Vec::with_capacity(height);
+        let mut column_cdfs = Vec::with_capacity(width * height);
+        let mut cumulative_row = 0.;
+        for row in pixel_probabilities.chunks(width) {
+            let row_probability: f32 = row.iter().sum();
+            cumulative_row += row_probability;
+            row_cdf.push(cumulative_row);
+
+            let mut cumulative_column = 0.;
+            for probability in row {
+                cumulative_column += probability;
+                column_cdfs.push(match row_probability > 0. {
+                    true => cumulative_column / row_probability,
+                    false => 1.,
+                });
+            }
+        }
+
+        return Ok(Self {
+            image,
+            rotation: 0.,
+            intensity: 1.,
+            row_cdf,
+            column_cdfs,
+            pixel_probabilities,
+        });
+    }
+
+    /// Rotates the map around the vertical axis.
+    pub fn with_rotation(self, rotation: Degrees) -> Self {
+        return Self {
+            rotation: rotation.to_radians().as_f32(),
+            ..self
+        };
+    }
+
+    /// Scales the radiance of the map.
+    pub fn with_intensity(self, intensity: f32) -> Self {
+        return Self { intensity, ..self };
+    }
+
+    /// Returns the coordinates of `direction` in the map, each in `[0, 1]`.
+    fn direction_to_uv(&self, direction: UnitVec3) -> (f32, f32) {
+        let direction = direction.as_vec3();
+        let phi = direction.z.atan2(direction.x) - self.rotation;
+        let theta = direction.y.clamp(-1., 1.).acos();
+        return ((phi / (2. * PI)).rem_euclid(1.), theta / PI);
+    }
+
+    fn uv_to_direction(&self, u: f32, v: f32) -> UnitVec3 {
+        let phi = 2. * PI * u + self.rotation;
+        let theta = PI * v;
+        return Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+        .normalize();
+    }
+
+    fn pixel_at(&self, u: f32, v: f32) -> (usize, usize) {
+        let x = ((u * self.image.width() as f32) as usize).min(self.image.width() as usize - 1);
+        let y = ((v * self.image.height() as f32) as usize).min(self.image.height() as usize - 1);
+        return (x, y);
+    }
+
+    /// Converts the probability of sampling a pixel to a density over solid angle,
+    /// at a direction `theta` away from the top of the map.
+    fn solid_angle_pdf(&self, pixel_probability: f32, theta: f32) -> f32 {
+        let sin_theta = theta.sin();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+        let pixel_count = (self.image.width() * self.image.height()) as f32;
+        return pixel_probability * pixel_count / (2. * PI * PI * sin_theta);
+    }
+}
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: UnitVec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, y) = self.pixel_at(u, v);
+        let [r, g, b] = self.image.get_pixel(x as u32, y as u32).0;
+        return Color::new(r, g, b) * self.intensity;
+    }
+
+    fn sample(&self) -> Option<(UnitVec3, Color, f32)> {
+        let width = self.image.width() as usize;
+        let height = self.image.height() as usize;
+
+        // Invert the CDFs with one random number each, not one per comparison
+        let r = random::<f32>();
+        let y = self.row_cdf.partition_point(|&cdf| cdf < r).min(height - 1);
+        let row = &self.column_cdfs[y * width..(y + 1) * width];
+        let r = random::<f32>();
+        let x = row.partition_point(|&cdf| cdf < r).min(width - 1);
+
+        let u = (x as f32 + random::<f32>()) / width as f32;
+        let v = (y as f32 + random::<f32>()) / height as f32;
+        let direction = self.uv_to_direction(u, v);
+        // Evaluated from the direction, like the radiance, so that both agree with `pdf`
+        // even where rounding moves the direction into a neighboring pixel
+        let pdf = self.pdf(direction);
+        if pdf <= 0. {
+            return None;
+        }
+
+        return Some((direction, self.radiance(direction), pdf));
+    }
+
+    fn pdf(&self, direction: UnitVec3) -> f32 {
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, y) = self.pixel_at(u, v);
+        let probability = self.pixel_probabilities[y * self.image.width() as usize + x];
+        return self.solid_angle_pdf(probability, PI * v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::approx_equals;
+
+    /// A dim map with a single bright pixel.
+    fn map_with_bright_pixel() -> EnvironmentMap {
+        let mut image = Rgb32FImage::from_pixel(16, 8, image::Rgb([0.1, 0.1, 0.1]));
+        image.put_pixel(5, 2, image::Rgb([100., 100., 100.]));
+        return EnvironmentMap::from_image(image)
+            .unwrap()
+            .with_rotation(Degrees(30.));
+    }
+
+    #[test]
+    fn empty_images_are_rejected() {
+        assert!(EnvironmentMap::from_image(Rgb32FImage::new(0, 8)).is_err());
+        assert!(EnvironmentMap::from_image(Rgb32FImage::new(16, 0)).is_err());
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let map = map_with_bright_pixel();
+        // Midpoint rule on a grid much finer than the pixels, as random directions
+        // rarely land on the bright pixel which holds most of the density
+        let (rows, columns) = (512, 1024);
+        let (d_theta, d_phi) = (PI / rows as f32, 2. * PI / columns as f32);
+        let mut integral = 0.;
+        for row in 0..rows {
+            let theta = (row as f32 + 0.5) * d_theta;
+            for column in 0..columns {
+                let phi = (column as f32 + 0.5) * d_phi;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                integral += map.pdf(direction.normalize()) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        assert!((integral - 1.).abs() < 0.01, "integral = {integral}");
+    }
+
+    #[test]
+    fn samples_favor_bright_pixels() {
+        let map = map_with_bright_pixel();
+        let samples = 10_000;
+        let mut bright = 0;
+        // Samples are rejected only at the poles, where the density is zero
+        for (direction, radiance, pdf) in (0..samples).filter_map(|_| map.sample()) {
+            assert!(approx_equals(pdf, map.pdf(direction)));
+            if radiance.r > 1. {
+                bright += 1;
+            }
+        }
+        assert!(bright > samples * 9 / 10);
+    }
+
+    #[test]
+    fn sample_histogram_matches_pdf() {
+        let map = map_with_bright_pixel();
+        let (width, height) = (16, 8);
+        let samples = 200_000;
+        let mut counts = vec![0; width * height];
+        for (direction, _, _) in (0..samples).filter_map(|_| map.sample()) {
+            let (u, v) = map.direction_to_uv(direction);
+            let (x, y) = map.pixel_at(u, v);
+            counts[y * width + x] += 1;
+        }
+
+        // The probability of each pixel is its pdf times its solid angle, both taken at its center
+        for y in 0..height {
+            for x in 0..width {
+                let u = (x as f32 + 0.5) / width as f32;
+                let v = (y as f32 + 0.5) / height as f32;
+                let solid_angle = (2. * PI / width as f32) * (PI / height as f32) * (PI * v).sin();
+                let expected = map.pdf(map.uv_to_direction(u, v)) * solid_angle;
+                let actual = counts[y * width + x] as f32 / samples as f32;
+                assert!(
+                    (actual - expected).abs() < 0.005,
+                    "pixel ({x}, {y}): expected {expected}, sampled {actual}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An error loading an asset, such as a texture, a mesh or a light profile,
+/// which names the file at fault.
+#[derive(Debug)]
+pub enum Error {
+    /// The file could not be read.
+    Io { path: PathBuf, source: io::Error },
+    /// The file could not be decoded as an image.
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// The file is malformed, on the given line if known.
+    Invalid {
+        path: PathBuf,
+        line: Option<usize>,
+        message: String,
+    },
+}
+impl Error {
+    pub fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        return Self::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        };
+    }
+
+    pub fn image(path: impl AsRef<Path>, source: image::ImageError) -> Self {
+        return Self::Image {
+            path: path.as_ref().to_path_buf(),
+            source,
+        };
+    }
+
+    /// Returns an error for malformed contents, where `line` counts from 1.
+    pub fn invalid(
+        path: impl AsRef<Path>,
+        line: Option<usize>,
+        message: impl Into<String>,
+    ) -> Self {
+        return Self::Invalid {
+            path: path.as_ref().to_path_buf(),
+            line,
+            message: message.into(),
+        };
+    }
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        return match self {
+            Error::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Error::Image { path, source } => write!(f, "{}: {source}", path.display()),
+            Error::Invalid {
+                path,
+                line: Some(line),
+                message,
+            } => write!(f, "{}:{line}: {message}", path.display()),
+            Error::Invalid {
+                path,
+                line: None,
+                message,
+            } => write!(f, "{}: {message}", path.display()),
+        };
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Image { source, .. } => Some(source),
+            Error::Invalid { .. } => None,
+        };
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::ImageTexture;
+    use crate::irt::{ColorSpace, IesProfile};
+
+    #[test]
+    fn messages_name_the_file_and_line() {
+        let error = Error::invalid("mesh.tri", Some(12), "expected 9 coordinates, found 8");
+        assert_eq!(
+            error.to_string(),
+            "mesh.tri:12: expected 9 coordinates, found 8"
+        );
+
+        let error = ImageTexture::new("missing.png", ColorSpace::Srgb).unwrap_err();
+        assert!(matches!(error, Error::Image { .. }));
+        assert!(error.to_string().starts_with("missing.png: "));
+
+        let error = IesProfile::load("missing.ies").unwrap_err();
+        assert!(matches!(error, Error::Io { .. }));
+        assert!(error.to_string().starts_with("missing.ies: "));
+    }
+}
@@ -1,14 +1,22 @@
-use crate::irt::{Aabb, Interval, Material, Point, Ray, UnitVec3};
+use crate::irt::{Aabb, Interval, Material, Matrix, Onb, Point, Ray, UnitVec3, Vec3};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Hit<'a> {
     pub point: Point,
     pub normal: UnitVec3,
+    /// The direction in which the `u` texture coordinate increases, along the surface.
+    /// Orients anisotropic materials and tangent-space normal maps.
+    pub tangent: Vec3,
+    /// The direction in which the `v` texture coordinate increases, along the surface.
+    pub bitangent: Vec3,
     pub t: f32,
     pub front_face: bool,
     pub material: &'a dyn Material,
     pub u: f32,
     pub v: f32,
+    /// The width of the cone of the ray at the hit, in world units.
+    /// Zero for rays without a cone, which see textures at full detail.
+    pub cone_width: f32,
 }
 impl<'a> Hit<'a> {
     pub fn new(
@@ -26,20 +34,68 @@ impl<'a> Hit<'a> {
             false => -outward_normal,
         };
 
+        let onb = Onb::new(outward_normal);
         return Self {
             point,
             normal,
+            tangent: onb.u,
+            bitangent: onb.v,
             t,
             front_face,
             material,
             u,
             v,
+            cone_width: ray.cone_width_at(t),
         };
     }
+
+    /// Sets the tangent and bitangent of the surface, for geometry with texture coordinates.
+    /// Their lengths are the rates of change of the point with `u` and `v`.
+    pub fn with_tangents(self, tangent: Vec3, bitangent: Vec3) -> Self {
+        return Self {
+            tangent,
+            bitangent,
+            ..self
+        };
+    }
+
+    /// Returns the width of the ray cone at the hit in texture coordinates,
+    /// measured along the tangent, which gives the rate of change of the point with `u`.
+    pub fn texture_footprint(&self) -> f32 {
+        let tangent_length = self.tangent.length();
+        if tangent_length == 0. {
+            return 0.;
+        }
+        return self.cone_width / tangent_length;
+    }
+
+    /// Returns the shading frame of the hit, with the normal as `w` and the tangent as `u`.
+    pub fn frame(&self) -> Onb {
+        return Onb::from_tangent(self.normal, self.tangent);
+    }
+
+    /// Transforms a hit in object space to world space,
+    /// given the object-to-world transform and its inverse.
+    pub fn transform(&mut self, transform: &Matrix, inverse_transform: &Matrix) {
+        self.point = *transform * self.point;
+        self.normal = (inverse_transform.transpose() * self.normal.as_vec3()).normalize();
+        self.tangent = *transform * self.tangent;
+        self.bitangent = *transform * self.bitangent;
+    }
 }
 
-pub trait Hittable: Sync {
-    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit>;
+pub trait Hittable: Send + Sync {
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>>;
+
+    /// Returns whether the ray hits anything within `t_interval`.
+    ///
+    /// Unlike [`Hittable::hit`], which finds the closest hit, this may stop
+    /// at the first intersection found and never builds a [`Hit`].
+    /// Used for shadow rays and similar visibility queries.
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        let mut t_interval = *t_interval;
+        return self.hit(ray, &mut t_interval).is_some();
+    }
 
     // TODO: Should this be defined to be in either local or world space?
     fn bounds(&self) -> Aabb;
@@ -50,13 +106,19 @@ pub trait Hittable: Sync {
     }
 }
 impl<T: Hittable> Hittable for [T] {
-    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
         return self
             .iter()
             .filter_map(|hittable| hittable.hit(ray, t_interval))
             .min_by(|x, y| x.t.total_cmp(&y.t));
     }
 
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        return self
+            .iter()
+            .any(|hittable| hittable.occluded(ray, t_interval));
+    }
+
     fn bounds(&self) -> Aabb {
         return self
             .iter()
@@ -69,10 +131,14 @@ impl<T: Hittable> Hittable for [T] {
 // containing only a single primitive. This might be pre-mature
 // optimization, but it is cool!
 impl Hittable for &dyn Hittable {
-    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
         return (*self).hit(ray, t_interval);
     }
 
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        return (*self).occluded(ray, t_interval);
+    }
+
     fn bounds(&self) -> Aabb {
         return (*self).bounds();
     }
@@ -0,0 +1,249 @@
+use std::fs;
+use std::path::Path;
+
+use crate::irt::{lerp, Error, Result};
+
+/// A photometric profile in the IES LM-63 format, describing how the intensity of a
+/// luminaire varies with the direction of emission.
+///
+/// Only type C photometry is supported, the format of nearly all architectural
+/// luminaires: vertical angles go from 0° straight down the axis of the luminaire to 180°
+/// straight up, and horizontal angles go around that axis.
+///
+/// Only the shape of the profile is kept, so the lumens and the candela multiplier of the
+/// file are ignored: the power of a light is given by its material.
+#[derive(Debug, Clone)]
+pub struct IesProfile {
+    /// Vertical angles in degrees, in increasing order.
+    vertical_angles: Vec<f32>,
+    /// Horizontal angles in degrees, in increasing order.
+    horizontal_angles: Vec<f32>,
+    /// The candela values for each horizontal angle, then each vertical angle,
+    /// divided by the largest value.
+    intensities: Vec<f32>,
+    /// See [`IesProfile::projected_solid_angle`].
+    projected_solid_angle: f32,
+}
+impl IesProfile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        return Self::parse(&text).map_err(|message| Error::invalid(path, None, message));
+    }
+
+    fn parse(text: &str) -> Result<Self, &'static str> {
+        // Keywords come first, up to the line describing the tilt of the lamp
+        let mut lines = text.lines();
+        let tilt = lines
+            .find(|line| line.trim_start().starts_with("TILT="))
+            .ok_or("IES file has no TILT line")?;
+        if tilt.trim() != "TILT=NONE" {
+            return Err("IES files with a lamp tilt are not supported");
+        }
+
+        let numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse::<f32>()
+                    .map_err(|_| "IES file has an invalid number")
+            })
+            .collect::<Result<Vec<f32>, _>>()?;
+
+        // Lamp count, lumens per lamp and candela multiplier, angle counts,
+        // photometric type, units and dimensions, then ballast factors and watts
+        if numbers.len() < 13 {
+            return Err("IES file is truncated");
+        }
+        let values = &numbers[13..];
+        let vertical_count = angle_count(numbers[3], values.len())?;
+        let horizontal_count = angle_count(numbers[4], values.len())?;
+        if numbers[5] != 1. {
+            return Err("only type C IES photometry is supported");
+        }
+
+        let value_count = vertical_count
+            .checked_mul(horizontal_count)
+            .and_then(|count| count.checked_add(vertical_count + horizontal_count));
+        if value_count.is_none_or(|count| values.len() < count) {
+            return Err("IES file is truncated");
+        }
+        let (vertical_angles, values) = values.split_at(vertical_count);
+        let (horizontal_angles, values) = values.split_at(horizontal_count);
+        if !increases(vertical_angles) || !increases(horizontal_angles) {
+            return Err("IES file has angles that do not strictly increase");
+        }
+        let candelas = &values[..vertical_count * horizontal_count];
+
+        let max = candelas.iter().copied().fold(0., f32::max);
+        let intensities = match max > 0. {
+            true => candelas.iter().map(|candela| candela / max).collect(),
+            false => candelas.to_vec(),
+        };
+
+        let mut profile = Self {
+            vertical_angles: vertical_angles.to_vec(),
+            horizontal_angles: horizontal_angles.to_vec(),
+            intensities,
+            projected_solid_angle: 0.,
+        };
+        profile.projected_solid_angle = profile.integrate_projected_solid_angle();
+
+        return Ok(profile);
+    }
+
+    /// Returns the integral of the relative intensity, weighted by the cosine of the
+    /// vertical angle, over the vertical angles from 0° to 90°.
+    ///
+    /// A surface emitting radiance `L` shaped by the profile emits this times `L` per unit
+    /// of area from each side, instead of `π * L` for a uniform profile.
+    pub fn projected_solid_angle(&self) -> f32 {
+        return self.projected_solid_angle;
+    }
+
+    /// Integrates [`IesProfile::projected_solid_angle`] with the midpoint rule,
+    /// in steps of a degree.
+    fn integrate_projected_solid_angle(&self) -> f32 {
+        let step = 1_f32.to_radians();
+        let mut integral = 0.;
+        for vertical in 0..90 {
+            let theta = (vertical as f32 + 0.5) * step;
+            let weight = theta.cos() * theta.sin() * step * step;
+            for horizontal in 0..360 {
+                let phi = (horizontal as f32 + 0.5) * step;
+                integral += weight * self.intensity(theta.to_degrees(), phi.to_degrees());
+            }
+        }
+        return integral;
+    }
+
+    /// Returns the relative intensity, from 0 to 1, emitted `vertical` degrees away
+    /// from the axis of the luminaire and `horizontal` degrees around it.
+    pub fn intensity(&self, vertical: f32, horizontal: f32) -> f32 {
+        // Profiles only cover part of the horizontal angles when they are symmetric
+        let horizontal = horizontal.rem_euclid(360.);
+        let horizontal = match self.horizontal_angles.last() {
+            Some(&last) if last <= 0. => 0.,
+            Some(&last) if last <= 90. => match horizontal {
+                h if h <= 90. => h,
+                h if h <= 180. => 180. - h,
+                h if h <= 270. => h - 180.,
+                h => 360. - h,
+            },
+            Some(&last) if last <= 180. => match horizontal > 180. {
+                true => 360. - horizontal,
+                false => horizontal,
+            },
+            _ => horizontal,
+        };
+
+        let (h0, h1, h_weight) = Self::bracket(&self.horizontal_angles, horizontal);
+        let (v0, v1, v_weight) = Self::bracket(&self.vertical_angles, vertical);
+        let value = |h: usize, v: usize| self.intensities[h * self.vertical_angles.len() + v];
+
+        return lerp(
+            lerp(value(h0, v0), value(h0, v1), v_weight),
+            lerp(value(h1, v0), value(h1, v1), v_weight),
+            h_weight,
+        );
+    }
+
+    /// Returns the indices of the angles around `angle` and the weight of the second one.
+    /// Angles outside the range of `angles` are clamped to it.
+    fn bracket(angles: &[f32], angle: f32) -> (usize, usize, f32) {
+        let upper = angles.partition_point(|&a| a < angle);
+        if upper == 0 {
+            return (0, 0, 0.);
+        }
+        if upper == angles.len() {
+            return (upper - 1, upper - 1, 0.);
+        }
+
+        let (a0, a1) = (angles[upper - 1], angles[upper]);
+        return (upper - 1, upper, (angle - a0) / (a1 - a0));
+    }
+}
+
+/// Converts a count of angles to an integer, which must be at least 1
+/// and at most the `value_count` numbers following the header.
+fn angle_count(count: f32, value_count: usize) -> Result<usize, &'static str> {
+    if count.fract() != 0. || count < 1. || count > value_count as f32 {
+        return Err("IES file has an invalid number of angles");
+    }
+    return Ok(count as usize);
+}
+
+/// Returns whether each angle is greater than the one before.
+fn increases(angles: &[f32]) -> bool {
+    return angles.windows(2).all(|pair| pair[0] < pair[1]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::approx_equals;
+    use std::f32::consts::PI;
+
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[TEST] downlight
+[MANUFAC] none
+TILT=NONE
+1 1000 1 5 1 1 2 0.1 0.1 0
+1 1 20
+0 22.5 45 67.5 90
+0
+400 400 300, 100 0
+";
+
+    #[test]
+    fn parses_and_interpolates_symmetric_profile() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        assert_eq!(profile.intensity(0., 0.), 1.);
+        assert!(approx_equals(profile.intensity(45., 120.), 0.75));
+        assert!(approx_equals(profile.intensity(56.25, 300.), 0.5));
+        assert_eq!(profile.intensity(135., 0.), 0.);
+    }
+
+    #[test]
+    fn projected_solid_angle_of_uniform_profile_is_pi() {
+        let uniform = DOWNLIGHT.replace("400 400 300, 100 0", "5 5 5 5 5");
+        let profile = IesProfile::parse(&uniform).unwrap();
+        assert!((profile.projected_solid_angle() - PI).abs() < 1e-3);
+
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        assert!(profile.projected_solid_angle() < PI);
+    }
+
+    #[test]
+    fn rejects_invalid_angle_counts() {
+        for count in ["0", "-5", "2.5", "NaN", "inf", "1e9"] {
+            let header = format!("1 1000 1 {count} 1 1 2 0.1 0.1 0");
+            let invalid = DOWNLIGHT.replace("1 1000 1 5 1 1 2 0.1 0.1 0", &header);
+            assert!(IesProfile::parse(&invalid).is_err(), "count = {count}");
+        }
+    }
+
+    #[test]
+    fn rejects_angles_out_of_order() {
+        for vertical in ["0 22.5 45 45 90", "0 45 22.5 67.5 90", "0 22.5 NaN 67.5 90"] {
+            let invalid = DOWNLIGHT.replace("0 22.5 45 67.5 90", vertical);
+            assert!(
+                IesProfile::parse(&invalid).is_err(),
+                "vertical = {vertical}"
+            );
+        }
+
+        let two_planes = DOWNLIGHT
+            .replace("1 1000 1 5 1 1 2 0.1 0.1 0", "1 1000 1 5 2 1 2 0.1 0.1 0")
+            .replace("400 400 300, 100 0", "400 400 300 100 0 400 400 300 100 0");
+        assert!(IesProfile::parse(&two_planes.replace("\n0\n", "\n0 90\n")).is_ok());
+        assert!(IesProfile::parse(&two_planes.replace("\n0\n", "\n90 0\n")).is_err());
+    }
+
+    #[test]
+    fn rejects_tilted_lamps() {
+        let tilted = DOWNLIGHT.replace("TILT=NONE", "TILT=INCLUDE");
+        assert!(IesProfile::parse(&tilted).is_err());
+    }
+}
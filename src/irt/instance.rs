@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use crate::irt::{Aabb, Hit, Hittable, Interval, Material, Matrix, Ray};
+
+/// The kinds of rays an instance is visible to.
+#[derive(Debug, Clone, Copy)]
+pub struct Visibility {
+    /// Whether the instance is seen directly by the camera.
+    pub camera: bool,
+    /// Whether the instance casts shadows.
+    pub shadow: bool,
+    /// Whether the instance is seen in reflections, refractions, and diffuse bounces.
+    pub reflection: bool,
+}
+impl Visibility {
+    /// Visible to all rays.
+    pub const ALL: Self = Self {
+        camera: true,
+        shadow: true,
+        reflection: true,
+    };
+
+    /// Returns the visibility as a mask of [`Ray`] kinds.
+    pub fn mask(&self) -> u32 {
+        let mut mask = 0;
+        if self.camera {
+            mask |= Ray::CAMERA;
+        }
+        if self.shadow {
+            mask |= Ray::SHADOW;
+        }
+        if self.reflection {
+            mask |= Ray::REFLECTION;
+        }
+        return mask;
+    }
+}
+
+/// Replaces the materials of an instanced mesh, without modifying the mesh itself.
+#[derive(Debug, Clone, Default)]
+pub enum MaterialOverride {
+    /// Keep the materials of the mesh.
+    #[default]
+    None,
+    /// Use a single material for the whole instance.
+    Replace(Arc<dyn Material>),
+    /// Replace each material of the mesh by the material it is paired with.
+    /// Materials without a pair are kept.
+    Remap(Vec<(Arc<dyn Material>, Arc<dyn Material>)>),
+}
+impl MaterialOverride {
+    /// Applies the override to a hit on the instance.
+    pub fn apply<'a>(&'a self, hit: &mut Hit<'a>) {
+        match self {
+            MaterialOverride::None => {}
+            MaterialOverride::Replace(material) => hit.material = material.as_ref(),
+            MaterialOverride::Remap(slots) => {
+                // Materials are compared by identity, not by value
+                let slot = slots
+                    .iter()
+                    .find(|(from, _)| std::ptr::addr_eq(Arc::as_ptr(from), hit.material));
+                if let Some((_, to)) = slot {
+                    hit.material = to.as_ref();
+                }
+            }
+        }
+    }
+}
+
+/// A shared object, such as a BVH, placed in the world by a transform,
+/// with its own visibility and materials.
+pub struct Instance<H: Hittable + ?Sized> {
+    object: Arc<H>,
+    /// Object-to-world
+    transform: Matrix,
+    /// World-to-object
+    inverse_transform: Matrix,
+    /// Bounds in world space. Primarily used for building a TLAS
+    bounds: Aabb,
+    /// The kinds of rays the instance is visible to, see [`Ray::mask`].
+    mask: u32,
+    material: MaterialOverride,
+}
+impl<H: Hittable + ?Sized> Instance<H> {
+    /// Creates a new instance from an object and an object-to-world transform.
+    /// The instance is visible to all rays and keeps the materials of the object.
+    pub fn new(object: Arc<H>, transform: Matrix) -> Self {
+        let bounds = transform * object.bounds();
+        return Self {
+            object,
+            transform,
+            inverse_transform: transform.inverse(),
+            bounds,
+            mask: Ray::ALL,
+            material: MaterialOverride::None,
+        };
+    }
+
+    pub fn with_visibility(self, visibility: Visibility) -> Self {
+        return Self {
+            mask: visibility.mask(),
+            ..self
+        };
+    }
+
+    /// Renders the instance with other materials than those of the object.
+    pub fn with_material(self, material: MaterialOverride) -> Self {
+        return Self { material, ..self };
+    }
+
+    /// Returns a new instance of the same object, with `transform` applied
+    /// after the transform of this instance. Visibility and materials are kept.
+    pub fn transformed(&self, transform: Matrix) -> Self {
+        return Self {
+            mask: self.mask,
+            material: self.material.clone(),
+            ..Self::new(self.object.clone(), transform * self.transform)
+        };
+    }
+
+    /// Moves the instance to a new object-to-world transform.
+    ///
+    /// A TLAS holding the instance must be refitted afterwards, see
+    /// [`Tlas::refit`](crate::irt::Tlas::refit).
+    pub fn set_transform(&mut self, transform: Matrix) {
+        self.transform = transform;
+        self.inverse_transform = transform.inverse();
+        self.bounds = transform * self.object.bounds();
+    }
+}
+impl<H: Hittable + ?Sized> Hittable for Instance<H> {
+    fn bounds(&self) -> Aabb {
+        return self.bounds;
+    }
+
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        if ray.mask & self.mask == 0 {
+            return None;
+        }
+
+        let mut hit = self
+            .object
+            .hit(&(self.inverse_transform * *ray), t_interval)?;
+
+        // The hit is in object space and must therefore be transformed to world space
+        hit.transform(&self.transform, &self.inverse_transform);
+        self.material.apply(&mut hit);
+
+        return Some(hit);
+    }
+
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        return ray.mask & self.mask != 0
+            && self
+                .object
+                .occluded(&(self.inverse_transform * *ray), t_interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{
+        BVHInstance, Bvh, Color, Hittable, Interval, Lambertian, Matrix, Point, Quad, Vec3,
+    };
+
+    #[test]
+    fn remap_replaces_only_paired_materials() {
+        let white: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let red: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::new(1., 0., 0.))));
+        let cube = Arc::new(Bvh::new(Quad::cube(white.clone())));
+        let ray = Ray::new(Point::new(0.5, 0.5, -5.), Vec3::new(0., 0., 1.));
+
+        let remapped = BVHInstance::new(cube.clone(), Matrix::identity())
+            .with_material(MaterialOverride::Remap(vec![(white.clone(), red.clone())]));
+        let hit = remapped
+            .hit(&ray, &mut Interval::new(0.001, f32::INFINITY))
+            .unwrap();
+        assert!(std::ptr::addr_eq(hit.material, Arc::as_ptr(&red)));
+
+        let unrelated = BVHInstance::new(cube, Matrix::identity())
+            .with_material(MaterialOverride::Remap(vec![(red.clone(), red)]));
+        let hit = unrelated
+            .hit(&ray, &mut Interval::new(0.001, f32::INFINITY))
+            .unwrap();
+        assert!(std::ptr::addr_eq(hit.material, Arc::as_ptr(&white)));
+    }
+}
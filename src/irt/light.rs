@@ -0,0 +1,225 @@
+use std::f32::consts::PI;
+
+use rand::random;
+
+use crate::irt::{Color, Degrees, Onb, Point, UnitVec3, Vec3};
+
+/// Light arriving at a point from a [`Light`].
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    /// The direction from the point towards the light.
+    pub direction: UnitVec3,
+    /// The distance to the light, infinite for distant lights.
+    pub distance: f32,
+    /// The irradiance on a surface at the point facing the light.
+    pub irradiance: Color,
+}
+
+/// A light source without any geometry, which can only be reached by sampling it
+/// explicitly. See [`Camera::with_light`](crate::irt::Camera::with_light).
+pub trait Light: Send + Sync {
+    /// Samples the light arriving at `point`.
+    /// Returns `None` if the light does not reach `point`.
+    fn sample(&self, point: Point) -> Option<LightSample>;
+}
+
+/// A light emitting equally in all directions from a single point.
+#[derive(Debug)]
+pub struct PointLight {
+    position: Point,
+    /// The radiant intensity, i.e. the power emitted per unit of solid angle.
+    intensity: Color,
+}
+impl PointLight {
+    pub fn new(position: Point, intensity: Color) -> Self {
+        return Self {
+            position,
+            intensity,
+        };
+    }
+}
+impl Light for PointLight {
+    fn sample(&self, point: Point) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        // There is no direction towards a light at the point itself
+        if distance_squared <= 0. {
+            return None;
+        }
+
+        return Some(LightSample {
+            direction: to_light.normalize(),
+            distance: distance_squared.sqrt(),
+            irradiance: self.intensity * (1. / distance_squared),
+        });
+    }
+}
+
+/// A point light emitting within a cone.
+///
+/// The light is at full intensity inside the inner cone,
+/// and falls off smoothly to nothing at the outer cone.
+#[derive(Debug)]
+pub struct SpotLight {
+    position: Point,
+    /// The axis of the cone, pointing away from the light.
+    direction: UnitVec3,
+    /// The radiant intensity along the axis of the cone.
+    intensity: Color,
+    cos_inner_angle: f32,
+    cos_outer_angle: f32,
+}
+impl SpotLight {
+    /// Creates a spot light at `position` pointing at `look_at`.
+    /// The cone angles are measured from the axis of the cone.
+    pub fn new(
+        position: Point,
+        look_at: Point,
+        intensity: Color,
+        inner_angle: Degrees,
+        outer_angle: Degrees,
+    ) -> Self {
+        let cos_outer_angle = outer_angle.to_radians().as_f32().cos();
+        return Self {
+            position,
+            direction: (look_at - position).normalize(),
+            intensity,
+            cos_inner_angle: inner_angle.to_radians().as_f32().cos().max(cos_outer_angle),
+            cos_outer_angle,
+        };
+    }
+
+    /// Returns how much of the intensity is emitted in `direction`, from the light.
+    fn falloff(&self, direction: UnitVec3) -> f32 {
+        let cos_theta = direction.as_vec3().dot(self.direction.as_vec3());
+        if cos_theta >= self.cos_inner_angle {
+            return 1.;
+        }
+        if cos_theta <= self.cos_outer_angle {
+            return 0.;
+        }
+
+        let t = (cos_theta - self.cos_outer_angle) / (self.cos_inner_angle - self.cos_outer_angle);
+        return t * t * (3. - 2. * t);
+    }
+}
+impl Light for SpotLight {
+    fn sample(&self, point: Point) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared <= 0. {
+            return None;
+        }
+
+        let direction = to_light.normalize();
+        let falloff = self.falloff(-direction);
+        if falloff <= 0. {
+            return None;
+        }
+
+        return Some(LightSample {
+            direction,
+            distance: distance_squared.sqrt(),
+            irradiance: self.intensity * (falloff / distance_squared),
+        });
+    }
+}
+
+/// A light infinitely far away, such as the sun, arriving from the same direction everywhere.
+///
+/// The light may cover a small disk of the sky, which gives soft shadows.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    /// The direction towards the center of the light.
+    pub direction: UnitVec3,
+    /// The irradiance on a surface facing the light.
+    pub irradiance: Color,
+    cos_angular_radius: f32,
+}
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Color) -> Self {
+        return Self {
+            direction: direction.normalize(),
+            irradiance,
+            cos_angular_radius: 1.,
+        };
+    }
+
+    /// Spreads the light over a disk of the sky of the given angular radius.
+    pub fn with_angular_radius(self, angular_radius: Degrees) -> Self {
+        return Self {
+            cos_angular_radius: angular_radius.to_radians().as_f32().cos(),
+            ..self
+        };
+    }
+
+    /// Returns the radiance of the light's disk seen in `direction`,
+    /// or black if `direction` does not point at the disk or the disk has no area.
+    pub fn radiance(&self, direction: UnitVec3) -> Color {
+        let solid_angle = 2. * PI * (1. - self.cos_angular_radius);
+        if solid_angle <= 0.
+            || direction.as_vec3().dot(self.direction.as_vec3()) < self.cos_angular_radius
+        {
+            return Color::black();
+        }
+        return self.irradiance * (1. / solid_angle);
+    }
+
+    /// Samples a direction towards a uniformly chosen point of the light's disk.
+    fn sample_direction(&self) -> UnitVec3 {
+        let cos_theta = 1. - random::<f32>() * (1. - self.cos_angular_radius);
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        let phi = 2. * PI * random::<f32>();
+
+        return Onb::new(self.direction)
+            .local(Vec3::new(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                cos_theta,
+            ))
+            .normalize();
+    }
+}
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Point) -> Option<LightSample> {
+        return Some(LightSample {
+            direction: self.sample_direction(),
+            distance: f32::INFINITY,
+            irradiance: self.irradiance,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::approx_equals;
+
+    #[test]
+    fn point_light_falls_off_with_squared_distance() {
+        let light = PointLight::new(Point::new(0., 4., 0.), Color::new(8., 8., 8.));
+        let sample = light.sample(Point::new(0., 2., 0.)).unwrap();
+        assert!(approx_equals(sample.distance, 2.));
+        assert!(approx_equals(sample.irradiance.r, 2.));
+        assert_eq!(sample.direction.as_vec3(), Vec3::new(0., 1., 0.));
+        assert!(light.sample(Point::new(0., 4., 0.)).is_none());
+    }
+
+    #[test]
+    fn spot_light_only_lights_its_cone() {
+        let light = SpotLight::new(
+            Point::new(0., 1., 0.),
+            Point::new(0., 0., 0.),
+            Color::white(),
+            Degrees(20.),
+            Degrees(30.),
+        );
+        let inside = light.sample(Point::new(0.1, 0., 0.)).unwrap();
+        assert!(approx_equals(inside.irradiance.r, 1. / 1.01));
+        let edge = light.sample(Point::new(25_f32.to_radians().tan(), 0., 0.));
+        assert!(edge.is_some_and(|edge| edge.irradiance.r > 0. && edge.irradiance.r < 0.5));
+        assert!(light.sample(Point::new(1., 0., 0.)).is_none());
+        assert!(light.sample(Point::new(0., 2., 0.)).is_none());
+        assert!(light.sample(Point::new(0., 1., 0.)).is_none());
+    }
+}
@@ -1,15 +1,46 @@
+use std::f32::consts::PI;
+
 use rand::random;
 
-use crate::irt::{Color, Hit, Point, Ray, Texture, UnitVec3};
+use crate::irt::{Color, Hit, IesProfile, Onb, Ray, Texture, UnitVec3, Vec3};
 use std::fmt::Debug;
 
-pub trait Material: Debug + Sync {
+pub trait Material: Debug + Send + Sync {
     fn scatter(&self, _ray_in: &Ray, _hit: &Hit) -> Option<(Ray, Color)> {
         return None;
     }
-    fn emitted(&self, _u: f32, _v: f32, _point: Point) -> Color {
+    /// Returns the light emitted at `hit` back along `ray_in`.
+    fn emitted(&self, _ray_in: &Ray, _hit: &Hit) -> Color {
         return Color::black();
     }
+
+    /// Returns the fraction of the light arriving from `direction` that is scattered back
+    /// along `ray_in`: the BSDF times the cosine of the incident angle, or the phase function.
+    ///
+    /// Used to sample light sources explicitly. Returns `None` for materials that only
+    /// scatter into single directions, such as mirrors, which light sources cannot be
+    /// sampled for.
+    fn eval(&self, _ray_in: &Ray, _hit: &Hit, _direction: UnitVec3) -> Option<Color> {
+        return None;
+    }
+
+    /// Returns the probability density over solid angle of [`Material::scatter`]
+    /// sampling `direction`. Only meaningful for materials implementing [`Material::eval`].
+    fn pdf(&self, _ray_in: &Ray, _hit: &Hit, _direction: UnitVec3) -> f32 {
+        return 0.;
+    }
+
+    /// Scatters like [`Material::scatter`], also returning whether the ray was scattered by a
+    /// part of the material left out of [`Material::eval`], such as the mirror coating of a
+    /// [`LayeredMaterial`]. Light sources were not sampled for such rays.
+    /// Only meaningful for materials implementing [`Material::eval`].
+    ///
+    /// [`LayeredMaterial`]: crate::irt::LayeredMaterial
+    fn scatter_specular(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color, bool)> {
+        return self
+            .scatter(ray_in, hit)
+            .map(|(scattered, attenuation)| (scattered, attenuation, false));
+    }
 }
 
 #[derive(Debug)]
@@ -29,35 +60,16 @@ impl Material for Lambertian {
         }
         let scattered_ray = Ray::new(hit.point, scatter_direction);
 
-        return Some((scattered_ray, self.texture.value(hit.u, hit.v, hit.point)));
+        return Some((scattered_ray, self.texture.value_at(hit)));
     }
-}
 
-#[derive(Debug)]
-pub struct Metal {
-    albedo: Color,
-    fuzz: f32,
-}
-impl Metal {
-    pub fn new(albedo: Color, fuzz: f32) -> Self {
-        return Self {
-            albedo,
-            fuzz: fuzz.clamp(0., 1.),
-        };
+    fn eval(&self, _ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> Option<Color> {
+        let cos_theta = hit.normal.as_vec3().dot(direction.as_vec3()).max(0.);
+        return Some(self.texture.value_at(hit) * (cos_theta / PI));
     }
-}
-impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
-        let mut reflected = ray_in.direction.reflect(hit.normal.as_vec3());
-        reflected = reflected.normalize().as_vec3() + (self.fuzz * UnitVec3::random().as_vec3());
-
-        // Check whether the reflection has been fuzzed below the surface
-        // If it has, have the surface absorb the ray
-        if reflected.dot(hit.normal.as_vec3()) <= 0. {
-            return None;
-        }
 
-        return Some((Ray::new(hit.point, reflected), self.albedo));
+    fn pdf(&self, _ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> f32 {
+        return hit.normal.as_vec3().dot(direction.as_vec3()).max(0.) / PI;
     }
 }
 
@@ -66,10 +78,45 @@ pub struct Dielectric {
     /// The refractive index of the material in a vacuum.
     /// ALternatively, the refractive index in the material's enclosing media.
     refraction_index: f32,
+    /// The fraction of each color absorbed per unit of distance travelled inside.
+    absorption: Color,
 }
 impl Dielectric {
     pub fn new(refraction_index: f32) -> Self {
-        return Self { refraction_index };
+        return Self {
+            refraction_index,
+            absorption: Color::black(),
+        };
+    }
+
+    /// Tints the material by absorbing light inside it, following the Beer-Lambert law,
+    /// so that `transmittance` of the light remains after travelling `distance` inside.
+    /// Thicker parts then look darker.
+    pub fn with_absorption(self, transmittance: Color, distance: f32) -> Self {
+        let coefficient = |transmittance: f32| -transmittance.clamp(1e-6, 1.).ln() / distance;
+        return Self {
+            absorption: Color::new(
+                coefficient(transmittance.r),
+                coefficient(transmittance.g),
+                coefficient(transmittance.b),
+            ),
+            ..self
+        };
+    }
+
+    /// Returns the light remaining after travelling from the previous hit to `hit`,
+    /// which is inside the material when leaving it through a back face.
+    fn transmittance(&self, ray_in: &Ray, hit: &Hit) -> Color {
+        if hit.front_face {
+            return Color::white();
+        }
+
+        let distance = hit.t * ray_in.direction.length();
+        return Color::new(
+            (-self.absorption.r * distance).exp(),
+            (-self.absorption.g * distance).exp(),
+            (-self.absorption.b * distance).exp(),
+        );
     }
 
     /// Compute reflectance with Schlick's approximation
@@ -95,21 +142,270 @@ impl Material for Dielectric {
             false => unit_in_direction.refract(hit.normal.as_vec3(), refractive_index_ratio),
         };
 
-        return Some((Ray::new(hit.point, out_direction), Color::white()));
+        return Some((
+            Ray::new(hit.point, out_direction),
+            self.transmittance(ray_in, hit),
+        ));
     }
 }
 
+/// Phase function of media that scatter light uniformly in all directions.
+#[derive(Debug)]
+pub struct Isotropic {
+    texture: Box<dyn Texture>,
+}
+impl Isotropic {
+    pub fn new(texture: Box<dyn Texture>) -> Self {
+        return Self { texture };
+    }
+}
+impl Material for Isotropic {
+    fn scatter(&self, _ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
+        let scattered_ray = Ray::new(hit.point, UnitVec3::random().as_vec3());
+        return Some((scattered_ray, self.texture.value_at(hit)));
+    }
+
+    fn eval(&self, _ray_in: &Ray, hit: &Hit, _direction: UnitVec3) -> Option<Color> {
+        return Some(self.texture.value_at(hit) * (1. / (4. * PI)));
+    }
+
+    fn pdf(&self, _ray_in: &Ray, _hit: &Hit, _direction: UnitVec3) -> f32 {
+        return 1. / (4. * PI);
+    }
+}
+
+/// The Henyey-Greenstein phase function, scattering light preferably forward or backward.
+/// <https://pbr-book.org/3ed-2018/Volume_Scattering/Phase_Functions>
+#[derive(Debug)]
+pub struct HenyeyGreenstein {
+    texture: Box<dyn Texture>,
+    /// The anisotropy, from -1 (fully backward) through 0 (isotropic) to 1 (fully forward).
+    g: f32,
+}
+impl HenyeyGreenstein {
+    pub fn new(texture: Box<dyn Texture>, g: f32) -> Self {
+        return Self {
+            texture,
+            g: g.clamp(-0.99, 0.99),
+        };
+    }
+
+    /// Samples the cosine of the angle between the incoming and scattered directions.
+    fn sample_cos_theta(&self) -> f32 {
+        let g = self.g;
+        let xi = random::<f32>();
+        if g.abs() < 1e-3 {
+            return 1. - 2. * xi;
+        }
+
+        let ratio = (1. - g * g) / (1. - g + 2. * g * xi);
+        return ((1. + g * g - ratio * ratio) / (2. * g)).clamp(-1., 1.);
+    }
+}
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
+        let cos_theta = self.sample_cos_theta();
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        let phi = 2. * PI * random::<f32>();
+
+        let onb = Onb::new(ray_in.direction.normalize());
+        let direction = onb.local(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+        let scattered_ray = Ray::new(hit.point, direction);
+
+        return Some((scattered_ray, self.texture.value_at(hit)));
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> Option<Color> {
+        let phase = self.pdf(ray_in, hit, direction);
+        return Some(self.texture.value_at(hit) * phase);
+    }
+
+    /// The phase function itself, as it is sampled exactly.
+    fn pdf(&self, ray_in: &Ray, _hit: &Hit, direction: UnitVec3) -> f32 {
+        let cos_theta = ray_in
+            .direction
+            .normalize()
+            .as_vec3()
+            .dot(direction.as_vec3());
+        let g = self.g;
+        return (1. - g * g) / (4. * PI * (1. + g * g - 2. * g * cos_theta).powf(1.5));
+    }
+}
+
+/// An emissive material, turning any geometry into an area light.
+///
+/// By default, the light emits the radiance given by its texture from both sides.
 #[derive(Debug)]
 pub struct DiffuseLight {
     texture: Box<dyn Texture>,
+    two_sided: bool,
+    /// The power emitted per unit of area, if the light is normalized by power
+    /// rather than given by its radiance.
+    exitance: Option<f32>,
+    profile: Option<IesProfile>,
 }
 impl DiffuseLight {
     pub fn new(texture: Box<dyn Texture>) -> Self {
-        return Self { texture };
+        return Self {
+            texture,
+            two_sided: true,
+            exitance: None,
+            profile: None,
+        };
+    }
+
+    /// Emits only from the front face of the geometry, the side its normal points to.
+    pub fn one_sided(self) -> Self {
+        return Self {
+            two_sided: false,
+            ..self
+        };
+    }
+
+    /// Emits `watts` in total from geometry of the given `area`, split between its sides.
+    /// The texture then only gives the color of the light, white being the full power.
+    /// With a profile, the power is still `watts`, only distributed by the profile.
+    pub fn with_power(self, watts: f32, area: f32) -> Self {
+        return Self {
+            exitance: Some(watts / area),
+            ..self
+        };
+    }
+
+    /// Shapes the emission by a photometric profile, whose axis is the normal of the
+    /// geometry. The horizontal angles of the profile are measured around the normal
+    /// from the tangent of the surface, which only matters for asymmetric profiles.
+    pub fn with_profile(self, profile: IesProfile) -> Self {
+        return Self {
+            profile: Some(profile),
+            ..self
+        };
     }
 }
 impl Material for DiffuseLight {
-    fn emitted(&self, u: f32, v: f32, point: Point) -> Color {
-        return self.texture.value(u, v, point);
+    fn emitted(&self, ray_in: &Ray, hit: &Hit) -> Color {
+        if !self.two_sided && !hit.front_face {
+            return Color::black();
+        }
+
+        let mut radiance = self.texture.value_at(hit);
+        if let Some(exitance) = self.exitance {
+            // A Lambertian emitter of radiance L emits pi * L per unit of area, per side,
+            // or less when shaped by a profile
+            let projected_solid_angle = self
+                .profile
+                .as_ref()
+                .map_or(PI, IesProfile::projected_solid_angle);
+            let sides = if self.two_sided { 2. } else { 1. };
+            radiance *= exitance / (projected_solid_angle * sides);
+        }
+
+        if let Some(profile) = &self.profile {
+            let outgoing = -ray_in.direction.normalize().as_vec3();
+            let onb = hit.frame();
+            let vertical = outgoing.dot(onb.w).clamp(-1., 1.).acos();
+            let horizontal = outgoing.dot(onb.v).atan2(outgoing.dot(onb.u));
+            radiance *= profile.intensity(vertical.to_degrees(), horizontal.to_degrees());
+        }
+
+        return radiance;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{approx_equals, Point};
+
+    #[test]
+    fn henyey_greenstein_mean_cosine_is_g() {
+        for g in [-0.6, 0., 0.3, 0.9] {
+            let phase_function = HenyeyGreenstein::new(Box::new(Color::white()), g);
+            let samples = 100_000;
+            let mean = (0..samples)
+                .map(|_| phase_function.sample_cos_theta())
+                .sum::<f32>()
+                / samples as f32;
+            assert!((mean - g).abs() < 0.01, "g = {g}, mean cosine = {mean}");
+        }
+    }
+
+    #[test]
+    fn one_sided_light_is_dark_from_behind() {
+        let light = DiffuseLight::new(Box::new(Color::white()))
+            .one_sided()
+            .with_power(4. * PI, 2.);
+        let normal = Vec3::new(0., -1., 0.).normalize();
+        let point = Point::new(0., 0., 0.);
+
+        let below = Ray::new(Point::new(0., -1., 0.), Vec3::new(0., 1., 0.));
+        let hit = Hit::new(&below, point, normal, 1., &light, 0., 0.);
+        assert!(approx_equals(light.emitted(&below, &hit).r, 2.));
+
+        let above = Ray::new(Point::new(0., 1., 0.), Vec3::new(0., -1., 0.));
+        let hit = Hit::new(&above, point, normal, 1., &light, 0., 0.);
+        assert_eq!(light.emitted(&above, &hit).r, 0.);
+    }
+
+    #[test]
+    fn profiled_light_emits_its_power() {
+        let path = std::env::temp_dir().join("irt_profiled_light.ies");
+        std::fs::write(
+            &path,
+            "TILT=NONE\n1 1000 1 5 1 1 2 0.1 0.1 0\n1 1 20\n0 22.5 45 67.5 90\n0\n400 400 300 100 0\n",
+        )
+        .unwrap();
+        let profile = IesProfile::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let light = DiffuseLight::new(Box::new(Color::white()))
+            .one_sided()
+            .with_power(4. * PI, 2.)
+            .with_profile(profile);
+        let normal = Vec3::new(0., -1., 0.).normalize();
+        let point = Point::new(0., 0., 0.);
+
+        // Integrates the emitted radiance over the hemisphere below the light
+        let (rows, columns) = (90, 180);
+        let (d_theta, d_phi) = (0.5 * PI / rows as f32, 2. * PI / columns as f32);
+        let mut exitance = 0.;
+        for row in 0..rows {
+            let theta = (row as f32 + 0.5) * d_theta;
+            for column in 0..columns {
+                let phi = (column as f32 + 0.5) * d_phi;
+                let outgoing = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    -theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let ray = Ray::new(point + outgoing, -outgoing);
+                let hit = Hit::new(&ray, point, normal, 1., &light, 0., 0.);
+                exitance +=
+                    light.emitted(&ray, &hit).r * theta.cos() * theta.sin() * d_theta * d_phi;
+            }
+        }
+        assert!((exitance - 2. * PI).abs() < 0.05, "exitance = {exitance}");
+    }
+
+    #[test]
+    fn glass_absorbs_light_over_distance() {
+        let glass = Dielectric::new(1.5).with_absorption(Color::new(0.5, 0.8, 1.), 1.);
+        let normal = Vec3::new(0., 1., 0.).normalize();
+
+        // Leaving the glass after travelling 2 units inside it
+        let inside = Ray::new(Point::new(0., -2., 0.), Vec3::new(0., 1., 0.));
+        let hit = Hit::new(&inside, Point::new(0., 0., 0.), normal, 2., &glass, 0., 0.);
+        let (_, attenuation) = glass.scatter(&inside, &hit).unwrap();
+        assert!(approx_equals(attenuation.r, 0.25));
+        assert!(approx_equals(attenuation.g, 0.64));
+        assert_eq!(attenuation.b, 1.);
+
+        // Entering the glass
+        let outside = Ray::new(Point::new(0., 2., 0.), Vec3::new(0., -1., 0.));
+        let hit = Hit::new(&outside, Point::new(0., 0., 0.), normal, 2., &glass, 0., 0.);
+        assert_eq!(glass.scatter(&outside, &hit).unwrap().1.r, 1.);
     }
 }
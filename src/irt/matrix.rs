@@ -196,7 +196,11 @@ impl Mul<Ray> for Matrix {
     type Output = Ray;
 
     fn mul(self, ray: Ray) -> Ray {
-        return Ray::new(self * ray.origin, self * ray.direction);
+        return Ray {
+            origin: self * ray.origin,
+            direction: self * ray.direction,
+            ..ray
+        };
     }
 }
 impl Mul<Aabb> for Matrix {
@@ -0,0 +1,337 @@
+use std::sync::Arc;
+
+use rand::random;
+
+use crate::irt::{
+    Aabb, Color, DensityField, HenyeyGreenstein, Hit, Hittable, Interval, Isotropic, Material, Ray,
+    Texture, UnitVec3, Vec3,
+};
+
+/// A participating medium of constant density, such as fog or smoke,
+/// filling the volume enclosed by a boundary.
+///
+/// Rays passing through the medium scatter after a random distance,
+/// with a probability of scattering proportional to the density.
+/// The boundary must be closed and convex.
+pub struct ConstantMedium<T: Hittable> {
+    boundary: T,
+    /// The negative reciprocal of the density, used to sample free-flight distances.
+    negative_inverse_density: f32,
+    phase_function: Arc<dyn Material>,
+}
+impl<T: Hittable> ConstantMedium<T> {
+    pub fn new(boundary: T, density: f32, albedo: Box<dyn Texture>) -> Self {
+        return Self {
+            boundary,
+            negative_inverse_density: -1. / density,
+            phase_function: Arc::new(Isotropic::new(albedo)),
+        };
+    }
+}
+impl<T: Hittable> Hittable for ConstantMedium<T> {
+    fn bounds(&self) -> Aabb {
+        return self.boundary.bounds();
+    }
+
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        let (entry_t, exit_t) = span_inside(&self.boundary, ray, t_interval)?;
+
+        let ray_length = ray.direction.length();
+        let distance_inside_boundary = (exit_t - entry_t) * ray_length;
+        let hit_distance = self.negative_inverse_density * (1. - random::<f32>()).ln();
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = entry_t + hit_distance / ray_length;
+        t_interval.max = t;
+
+        // The normal is arbitrary, as scattering in the medium does not depend on it
+        let normal = UnitVec3::new(Vec3::new(1., 0., 0.));
+        return Some(Hit::new(
+            ray,
+            ray.at(t),
+            normal,
+            t,
+            self.phase_function.as_ref(),
+            0.,
+            0.,
+        ));
+    }
+}
+
+/// Returns the part of `t_interval` along `ray` that lies inside `boundary`.
+fn span_inside(boundary: &impl Hittable, ray: &Ray, t_interval: &Interval) -> Option<(f32, f32)> {
+    // Find where the ray enters and exits the boundary, along the whole line of the ray,
+    // so that rays starting inside the medium are handled
+    let entry_t = boundary
+        .hit(ray, &mut Interval::new(f32::NEG_INFINITY, f32::INFINITY))?
+        .t;
+    let exit_t = boundary
+        .hit(ray, &mut Interval::new(entry_t + 0.0001, f32::INFINITY))?
+        .t;
+
+    let entry_t = entry_t.max(t_interval.min).max(0.);
+    let exit_t = exit_t.min(t_interval.max);
+    if entry_t >= exit_t {
+        return None;
+    }
+    return Some((entry_t, exit_t));
+}
+
+/// A participating medium whose density varies in space, such as clouds or explosions,
+/// filling the volume enclosed by a boundary.
+///
+/// Scattering is sampled with delta tracking, and shadow rays are attenuated with
+/// ratio tracking, both against the maximum density of the field.
+/// The boundary must be closed and convex.
+pub struct HeterogeneousMedium<T: Hittable, D: DensityField> {
+    boundary: T,
+    density: D,
+    phase_function: Arc<dyn Material>,
+}
+impl<T: Hittable, D: DensityField> HeterogeneousMedium<T, D> {
+    /// `g` is the anisotropy of the [`HenyeyGreenstein`] phase function of the medium.
+    pub fn new(boundary: T, density: D, albedo: Box<dyn Texture>, g: f32) -> Self {
+        return Self {
+            boundary,
+            density,
+            phase_function: Arc::new(HenyeyGreenstein::new(albedo, g)),
+        };
+    }
+
+    /// Steps along the ray by free-flight distances sampled against the maximum density,
+    /// calling `collide` with the density at each tentative collision until it returns `true`.
+    /// Returns the `t` of the accepted collision, or `None` if the ray left the medium.
+    fn track(
+        &self,
+        ray: &Ray,
+        entry_t: f32,
+        exit_t: f32,
+        mut collide: impl FnMut(f32) -> bool,
+    ) -> Option<f32> {
+        let max_density = self.density.max_density();
+        if max_density <= 0. {
+            return None;
+        }
+
+        let ray_length = ray.direction.length();
+        let mut t = entry_t;
+        loop {
+            t -= (1. - random::<f32>()).ln() / (max_density * ray_length);
+            if t >= exit_t {
+                return None;
+            }
+            if collide(self.density.density(ray.at(t))) {
+                return Some(t);
+            }
+        }
+    }
+}
+impl<T: Hittable, D: DensityField> Hittable for HeterogeneousMedium<T, D> {
+    fn bounds(&self) -> Aabb {
+        return self.boundary.bounds();
+    }
+
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        let (entry_t, exit_t) = span_inside(&self.boundary, ray, t_interval)?;
+
+        // Delta tracking: a tentative collision is real with a probability of the
+        // density over the maximum density, otherwise the ray continues unaffected
+        let max_density = self.density.max_density();
+        let t = self.track(ray, entry_t, exit_t, |density| {
+            random::<f32>() < density / max_density
+        })?;
+        t_interval.max = t;
+
+        // The normal is arbitrary, as scattering in the medium does not depend on it
+        let normal = UnitVec3::new(Vec3::new(1., 0., 0.));
+        return Some(Hit::new(
+            ray,
+            ray.at(t),
+            normal,
+            t,
+            self.phase_function.as_ref(),
+            0.,
+            0.,
+        ));
+    }
+
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        let Some((entry_t, exit_t)) = span_inside(&self.boundary, ray, t_interval) else {
+            return false;
+        };
+
+        // Ratio tracking: the transmittance is the product of the probabilities of
+        // each tentative collision being null. A random threshold turns it into a
+        // visibility whose expected value is the transmittance
+        let max_density = self.density.max_density();
+        let mut transmittance = 1.;
+        let threshold = random::<f32>();
+        let collision = self.track(ray, entry_t, exit_t, |density| {
+            transmittance *= 1. - density / max_density;
+            transmittance <= threshold
+        });
+        return collision.is_some();
+    }
+}
+
+/// A medium filling the whole scene, denser near the ground, such as the haze of the
+/// atmosphere giving distant objects an aerial perspective. See [`Camera::with_medium`].
+///
+/// The density falls off exponentially with height, as `density * exp(-y / scale_height)`,
+/// so rays leaving upwards only cross a finite amount of it.
+///
+/// [`Camera::with_medium`]: crate::irt::Camera::with_medium
+#[derive(Debug)]
+pub struct GlobalMedium {
+    /// The density at a height of 0.
+    density: f32,
+    scale_height: f32,
+    phase_function: HenyeyGreenstein,
+}
+impl GlobalMedium {
+    /// `g` is the anisotropy of the [`HenyeyGreenstein`] phase function of the medium.
+    pub fn new(density: f32, scale_height: f32, albedo: Color, g: f32) -> Self {
+        return Self {
+            density,
+            scale_height,
+            phase_function: HenyeyGreenstein::new(Box::new(albedo), g),
+        };
+    }
+
+    /// Returns the density at the origin of `ray`, and the vertical part of its direction
+    /// per unit of distance travelled.
+    fn density_and_slope(&self, ray: &Ray) -> (f32, f32) {
+        let density = self.density * (-ray.origin.y / self.scale_height).exp();
+        return (density, ray.direction.y / ray.direction.length());
+    }
+
+    /// Samples where `ray` scatters in the medium before `t_max`, by inverting the
+    /// optical depth along the ray.
+    pub fn hit(&self, ray: &Ray, t_max: f32) -> Option<Hit<'_>> {
+        let (density, slope) = self.density_and_slope(ray);
+        let optical_depth = -(1. - random::<f32>()).ln();
+
+        let distance = if slope.abs() < 1e-4 {
+            optical_depth / density
+        } else {
+            let k = optical_depth * slope / (density * self.scale_height);
+            if k >= 1. {
+                return None;
+            }
+            -self.scale_height / slope * (-k).ln_1p()
+        };
+
+        let t = distance / ray.direction.length();
+        if t >= t_max {
+            return None;
+        }
+
+        // The normal is arbitrary, as scattering in the medium does not depend on it
+        let normal = UnitVec3::new(Vec3::new(1., 0., 0.));
+        return Some(Hit::new(
+            ray,
+            ray.at(t),
+            normal,
+            t,
+            &self.phase_function,
+            0.,
+            0.,
+        ));
+    }
+
+    /// Returns the fraction of light transmitted along `ray` until `t_max`.
+    pub fn transmittance(&self, ray: &Ray, t_max: f32) -> f32 {
+        let (density, slope) = self.density_and_slope(ray);
+        let distance = t_max * ray.direction.length();
+
+        let optical_depth = if slope.abs() < 1e-4 {
+            density * distance
+        } else {
+            density * self.scale_height / slope * -(-distance * slope / self.scale_height).exp_m1()
+        };
+        return (-optical_depth).exp();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{Bvh, Color, Lambertian, Point, Quad, VoxelGrid};
+
+    #[test]
+    fn tracking_matches_beer_lambert_transmittance() {
+        // A unit cube of density 2, crossed along its whole width
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let bounds = Aabb::new(Point::new(0., 0., 0.), Point::new(1., 1., 1.));
+        let density = VoxelGrid::dense([2, 2, 2], vec![2.; 8], bounds);
+        let medium = HeterogeneousMedium::new(
+            Bvh::new(Quad::cube(material)),
+            density,
+            Box::new(Color::white()),
+            0.,
+        );
+        let ray = Ray::new(Point::new(0.5, 0.5, -1.), Vec3::new(0., 0., 2.));
+        let interval = Interval::new(0.001, f32::INFINITY);
+
+        let samples = 20_000;
+        let passed = (0..samples)
+            .filter(|_| {
+                medium
+                    .hit(&ray, &mut Interval::new(0.001, f32::INFINITY))
+                    .is_none()
+            })
+            .count();
+        let unoccluded = (0..samples)
+            .filter(|_| !medium.occluded(&ray, &interval))
+            .count();
+
+        let expected = (-2_f32).exp();
+        assert!((passed as f32 / samples as f32 - expected).abs() < 0.02);
+        assert!((unoccluded as f32 / samples as f32 - expected).abs() < 0.02);
+    }
+
+    #[test]
+    fn constant_medium_matches_beer_lambert_transmittance() {
+        // A unit cube of density 2, crossed along its whole width
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let medium =
+            ConstantMedium::new(Bvh::new(Quad::cube(material)), 2., Box::new(Color::white()));
+        let ray = Ray::new(Point::new(0.5, 0.5, -1.), Vec3::new(0., 0., 2.));
+
+        let samples = 20_000;
+        let passed = (0..samples)
+            .filter(|_| {
+                medium
+                    .hit(&ray, &mut Interval::new(0.001, f32::INFINITY))
+                    .is_none()
+            })
+            .count();
+
+        let expected = (-2_f32).exp();
+        assert!((passed as f32 / samples as f32 - expected).abs() < 0.02);
+    }
+
+    #[test]
+    fn global_medium_sampling_matches_transmittance() {
+        let medium = GlobalMedium::new(0.5, 2., Color::white(), 0.);
+        let samples = 20_000;
+
+        for direction in [
+            Vec3::new(1., 0., 0.),
+            Vec3::new(1., 1., 0.),
+            Vec3::new(0., -1., 3.),
+        ] {
+            let ray = Ray::new(Point::new(0., 1., 0.), direction);
+            let passed = (0..samples)
+                .filter(|_| medium.hit(&ray, 1.5).is_none())
+                .count();
+            let expected = medium.transmittance(&ray, 1.5);
+            assert!((passed as f32 / samples as f32 - expected).abs() < 0.02);
+        }
+        // Rays leaving upwards are never fully absorbed
+        let up = Ray::new(Point::new(0., 0., 0.), Vec3::new(0., 1., 0.));
+        assert!(medium.transmittance(&up, f32::INFINITY) > 0.);
+    }
+}
@@ -0,0 +1,524 @@
+use std::f32::consts::PI;
+
+use rand::random;
+
+use crate::irt::{Color, Hit, Material, Onb, Ray, UnitVec3, Vec3};
+
+/// The GGX (Trowbridge-Reitz) distribution of microfacet normals, with Smith shadowing.
+/// <https://jcgt.org/published/0003/02/03/paper.pdf>
+///
+/// Directions are expressed in a local frame where the macrosurface normal is `z`.
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    /// The roughness along `x`.
+    alpha_x: f32,
+    /// The roughness along `y`.
+    alpha_y: f32,
+}
+impl Ggx {
+    /// Below this roughness, the surface is treated as perfectly smooth.
+    const MIN_ALPHA: f32 = 1e-3;
+
+    /// Creates a distribution from a perceptual `roughness` from 0 to 1, stretched
+    /// along `x` by an `anisotropy` from 0 (isotropic) to 1.
+    pub fn new(roughness: f32, anisotropy: f32) -> Self {
+        let alpha = roughness.clamp(0., 1.).powi(2);
+        let aspect = (1. - 0.9 * anisotropy.clamp(0., 1.)).sqrt();
+        return Self {
+            alpha_x: alpha / aspect,
+            alpha_y: alpha * aspect,
+        };
+    }
+
+    /// Whether the surface is so smooth it should be treated as a perfect mirror.
+    pub fn is_smooth(&self) -> bool {
+        return self.alpha_x.max(self.alpha_y) < Self::MIN_ALPHA;
+    }
+
+    /// The density of microfacets with normal `m`.
+    pub fn d(&self, m: Vec3) -> f32 {
+        if m.z <= 0. {
+            return 0.;
+        }
+        let e = (m.x / self.alpha_x).powi(2) + (m.y / self.alpha_y).powi(2) + m.z * m.z;
+        return 1. / (PI * self.alpha_x * self.alpha_y * e * e);
+    }
+
+    /// The Smith auxiliary function, from which shadowing and masking are derived.
+    fn lambda(&self, w: Vec3) -> f32 {
+        let tan_theta_squared =
+            ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / (w.z * w.z);
+        return ((1. + tan_theta_squared).sqrt() - 1.) / 2.;
+    }
+
+    /// The fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: Vec3) -> f32 {
+        return 1. / (1. + self.lambda(w));
+    }
+
+    /// The fraction of microfacets visible from both `wo` and `wi`, height-correlated.
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f32 {
+        return 1. / (1. + self.lambda(wo) + self.lambda(wi));
+    }
+
+    /// Samples a microfacet normal visible from `wo`, with a density of
+    /// `g1(wo) * max(0, wo·m) * d(m) / wo.z`.
+    /// <https://jcgt.org/published/0007/04/01/paper.pdf>
+    pub fn sample_visible_normal(&self, wo: Vec3) -> Vec3 {
+        // Stretch the view direction to a hemisphere configuration
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z)
+            .normalize()
+            .as_vec3();
+
+        let length_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = match length_squared > 0. {
+            true => Vec3::new(-vh.y, vh.x, 0.) / length_squared.sqrt(),
+            false => Vec3::new(1., 0., 0.),
+        };
+        let t2 = vh.cross(t1);
+
+        // Sample the projected area of the visible hemisphere
+        let r = random::<f32>().sqrt();
+        let phi = 2. * PI * random::<f32>();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + vh.z);
+        let p2 = (1. - s) * (1. - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1. - p1 * p1 - p2 * p2).max(0.).sqrt() * vh;
+
+        // Unstretch back to the ellipsoid configuration
+        return Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6))
+            .normalize()
+            .as_vec3();
+    }
+
+    /// The density of [`Ggx::sample_visible_normal`] sampling `m` from `wo`.
+    pub fn visible_normal_pdf(&self, wo: Vec3, m: Vec3) -> f32 {
+        return self.g1(wo) * wo.dot(m).max(0.) * self.d(m) / wo.z;
+    }
+}
+
+/// Fresnel reflectance of a conductor with complex index of refraction `eta + i k`,
+/// for light arriving at `cos_theta` from the normal.
+/// <https://seblagarde.wordpress.com/2013/04/29/memo-on-fresnel-equations/>
+fn conductor_fresnel(cos_theta: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_theta.clamp(0., 1.).powi(2);
+    let sin2 = 1. - cos2;
+
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.).sqrt();
+    let t2 = 2. * a * cos2.sqrt();
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    return 0.5 * (rp + rs);
+}
+
+/// A metal, reflecting light off a rough surface of GGX microfacets.
+///
+/// The color comes from the complex index of refraction `eta + i k`
+/// of the red, green and blue channels.
+///
+/// Anisotropy stretches highlights along the tangent of the surface, [`Hit::tangent`].
+#[derive(Debug)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    roughness: f32,
+    distribution: Ggx,
+}
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f32) -> Self {
+        return Self {
+            eta,
+            k,
+            roughness,
+            distribution: Ggx::new(roughness, 0.),
+        };
+    }
+
+    pub fn gold(roughness: f32) -> Self {
+        return Self::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+        );
+    }
+
+    pub fn copper(roughness: f32) -> Self {
+        return Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+        );
+    }
+
+    pub fn aluminium(roughness: f32) -> Self {
+        return Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        );
+    }
+
+    /// Creates a conductor reflecting `reflectance` of the light arriving along the normal.
+    /// Convenient for metals without measured indices of refraction.
+    pub fn from_reflectance(reflectance: Color, roughness: f32) -> Self {
+        // With eta = 1, the reflectance along the normal is k² / (4 + k²)
+        let k = |r: f32| 2. * (r.clamp(0., 0.999) / (1. - r.clamp(0., 0.999))).sqrt();
+        return Self::new(
+            Color::white(),
+            Color::new(k(reflectance.r), k(reflectance.g), k(reflectance.b)),
+            roughness,
+        );
+    }
+
+    /// Stretches the highlights of the surface, from 0 (isotropic) to 1.
+    pub fn with_anisotropy(self, anisotropy: f32) -> Self {
+        return Self {
+            distribution: Ggx::new(self.roughness, anisotropy),
+            ..self
+        };
+    }
+
+    fn fresnel(&self, cos_theta: f32) -> Color {
+        return Color::new(
+            conductor_fresnel(cos_theta, self.eta.r, self.k.r),
+            conductor_fresnel(cos_theta, self.eta.g, self.k.g),
+            conductor_fresnel(cos_theta, self.eta.b, self.k.b),
+        );
+    }
+
+    /// Returns the local frame of `hit` and the outgoing direction of `ray_in` in it.
+    fn local_frame(ray_in: &Ray, hit: &Hit) -> (Onb, Vec3) {
+        let onb = hit.frame();
+        let wo = onb.world_to_local(-ray_in.direction.normalize().as_vec3());
+        return (onb, wo);
+    }
+}
+impl Material for Conductor {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
+        let (onb, wo) = Self::local_frame(ray_in, hit);
+        if wo.z <= 0. {
+            return None;
+        }
+
+        if self.distribution.is_smooth() {
+            let wi = Vec3::new(-wo.x, -wo.y, wo.z);
+            return Some((Ray::new(hit.point, onb.local(wi)), self.fresnel(wo.z)));
+        }
+
+        let m = self.distribution.sample_visible_normal(wo);
+        let wi = 2. * wo.dot(m) * m - wo;
+        if wi.z <= 0. {
+            return None;
+        }
+
+        // The BSDF times the cosine over the density of visible normal sampling
+        let weight = self.distribution.g(wo, wi) / self.distribution.g1(wo);
+        return Some((
+            Ray::new(hit.point, onb.local(wi)),
+            self.fresnel(wo.dot(m)) * weight,
+        ));
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> Option<Color> {
+        if self.distribution.is_smooth() {
+            return None;
+        }
+
+        let (onb, wo) = Self::local_frame(ray_in, hit);
+        let wi = onb.world_to_local(direction.as_vec3());
+        if wo.z <= 0. || wi.z <= 0. {
+            return Some(Color::black());
+        }
+
+        let m = (wo + wi).normalize().as_vec3();
+        let d = self.distribution.d(m);
+        let g = self.distribution.g(wo, wi);
+        return Some(self.fresnel(wo.dot(m)) * (d * g / (4. * wo.z)));
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> f32 {
+        let (onb, wo) = Self::local_frame(ray_in, hit);
+        let wi = onb.world_to_local(direction.as_vec3());
+        if self.distribution.is_smooth() || wo.z <= 0. || wi.z <= 0. {
+            return 0.;
+        }
+
+        let m = (wo + wi).normalize().as_vec3();
+        return self.distribution.visible_normal_pdf(wo, m) / (4. * wo.dot(m));
+    }
+}
+
+/// Fresnel reflectance of a dielectric interface for light arriving at `cos_theta` from
+/// the normal, where `eta` is the ratio of the refractive index on the far side of the
+/// interface to the one on the near side. Returns 1 under total internal reflection.
+pub fn dielectric_fresnel(cos_theta: f32, eta: f32) -> f32 {
+    let cos_i = cos_theta.clamp(0., 1.);
+    let sin_t_squared = (1. - cos_i * cos_i) / (eta * eta);
+    if sin_t_squared >= 1. {
+        return 1.;
+    }
+
+    let cos_t = (1. - sin_t_squared).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    return 0.5 * (rs * rs + rp * rp);
+}
+
+/// Refracts `wo`, pointing away from the surface, through a microfacet with normal `m`.
+/// Returns `None` under total internal reflection.
+pub fn refract(wo: Vec3, m: Vec3, eta: f32) -> Option<Vec3> {
+    let cos_i = wo.dot(m);
+    let sin_t_squared = (1. - cos_i * cos_i).max(0.) / (eta * eta);
+    if sin_t_squared >= 1. {
+        return None;
+    }
+
+    let cos_t = (1. - sin_t_squared).sqrt();
+    return Some(-wo / eta + (cos_i / eta - cos_t) * m);
+}
+
+/// Returns the microfacet normal which scatters `wo` into `wi`, facing the macrosurface
+/// normal, and whether the light is transmitted through a dielectric of relative index `eta`.
+pub fn half_vector(wo: Vec3, wi: Vec3, eta: f32) -> (Vec3, bool) {
+    let transmitted = wi.z < 0.;
+    let m = match transmitted {
+        true => wo + eta * wi,
+        false => wo + wi,
+    };
+    let m = m.normalize().as_vec3();
+    return (if m.z < 0. { -m } else { m }, transmitted);
+}
+
+/// Glass with a rough surface of GGX microfacets, such as frosted glass, which both
+/// reflects and blurs the light it transmits.
+/// <https://www.graphics.cornell.edu/~bjw/microfacetbsdf.pdf>
+#[derive(Debug)]
+pub struct RoughDielectric {
+    /// The refractive index of the material in a vacuum.
+    refraction_index: f32,
+    distribution: Ggx,
+}
+impl RoughDielectric {
+    pub fn new(refraction_index: f32, roughness: f32) -> Self {
+        return Self {
+            refraction_index,
+            distribution: Ggx::new(roughness, 0.),
+        };
+    }
+
+    /// Returns the local frame of `hit`, the outgoing direction of `ray_in` in it,
+    /// and the ratio of the refractive index behind the surface to the one in front.
+    fn local_frame(&self, ray_in: &Ray, hit: &Hit) -> (Onb, Vec3, f32) {
+        let (onb, wo) = Conductor::local_frame(ray_in, hit);
+        let eta = match hit.front_face {
+            true => self.refraction_index,
+            false => 1. / self.refraction_index,
+        };
+        return (onb, wo, eta);
+    }
+}
+impl Material for RoughDielectric {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
+        let (onb, wo, eta) = self.local_frame(ray_in, hit);
+        if wo.z <= 0. {
+            return None;
+        }
+
+        let m = match self.distribution.is_smooth() {
+            true => Vec3::new(0., 0., 1.),
+            false => self.distribution.sample_visible_normal(wo),
+        };
+
+        // Choose between reflection and refraction in proportion to the Fresnel reflectance,
+        // which cancels out of the weight
+        let wi = match dielectric_fresnel(wo.dot(m), eta) > random() {
+            true => 2. * wo.dot(m) * m - wo,
+            false => refract(wo, m, eta)?,
+        };
+        let weight = match self.distribution.is_smooth() {
+            true => 1.,
+            false => self.distribution.g(wo, wi) / self.distribution.g1(wo),
+        };
+
+        // Microfacets may scatter light to the wrong side of the macrosurface
+        let transmitted = wo.dot(m) * wi.dot(m) < 0.;
+        if transmitted != (wi.z < 0.) {
+            return None;
+        }
+
+        return Some((
+            Ray::new(hit.point, onb.local(wi)),
+            Color::new(weight, weight, weight),
+        ));
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> Option<Color> {
+        if self.distribution.is_smooth() {
+            return None;
+        }
+
+        let (onb, wo, eta) = self.local_frame(ray_in, hit);
+        let wi = onb.world_to_local(direction.as_vec3());
+        if wo.z <= 0. || wi.z == 0. {
+            return Some(Color::black());
+        }
+
+        let (m, transmitted) = half_vector(wo, wi, eta);
+        let (cos_o, cos_i) = (wo.dot(m), wi.dot(m));
+        // Only microfacets facing both directions, from the appropriate sides, contribute
+        if cos_o <= 0. || (cos_i < 0.) != transmitted {
+            return Some(Color::black());
+        }
+
+        let fresnel = dielectric_fresnel(cos_o, eta);
+        let d = self.distribution.d(m);
+        let g = self.distribution.g(wo, wi);
+        let value = match transmitted {
+            true => {
+                let denominator = cos_o + eta * cos_i;
+                (1. - fresnel) * d * g * eta * eta * cos_o * -cos_i
+                    / (wo.z * denominator * denominator)
+            }
+            false => fresnel * d * g / (4. * wo.z),
+        };
+        return Some(Color::new(value, value, value));
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> f32 {
+        let (onb, wo, eta) = self.local_frame(ray_in, hit);
+        let wi = onb.world_to_local(direction.as_vec3());
+        if wo.z <= 0. || wi.z == 0. {
+            return 0.;
+        }
+
+        let (m, transmitted) = half_vector(wo, wi, eta);
+        let (cos_o, cos_i) = (wo.dot(m), wi.dot(m));
+        if cos_o <= 0. || (cos_i < 0.) != transmitted {
+            return 0.;
+        }
+
+        // The density of the microfacet normal, times the probability of the chosen event,
+        // times the change of variables from the normal to the scattered direction
+        let fresnel = dielectric_fresnel(cos_o, eta);
+        let normal_pdf = self.distribution.visible_normal_pdf(wo, m);
+        return match transmitted {
+            true => {
+                let denominator = cos_o + eta * cos_i;
+                normal_pdf * (1. - fresnel) * eta * eta * -cos_i / (denominator * denominator)
+            }
+            false => normal_pdf * fresnel / (4. * cos_o),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{approx_equals, Point};
+
+    #[test]
+    fn projected_microfacet_area_is_one() {
+        // The integral of D(m) m.z over the hemisphere, sampled uniformly
+        for (roughness, anisotropy) in [(0.5, 0.), (0.8, 0.), (0.7, 0.8)] {
+            let ggx = Ggx::new(roughness, anisotropy);
+            let samples = 200_000;
+            let integral = (0..samples)
+                .map(|_| {
+                    let mut m = UnitVec3::random().as_vec3();
+                    m.z = m.z.abs();
+                    ggx.d(m) * m.z * 2. * PI
+                })
+                .sum::<f32>()
+                / samples as f32;
+            assert!((integral - 1.).abs() < 0.05, "integral = {integral}");
+        }
+    }
+
+    #[test]
+    fn visible_normals_follow_their_density() {
+        // The mean of g1(wo) * wo·m / wo.z over sampled normals, divided by the density
+        // of sampling them, is the integral of D(m) m.z over visible normals: 1 for
+        // normals facing wo, a property which only holds if the sampling is correct
+        let ggx = Ggx::new(0.6, 0.5);
+        let wo = Vec3::new(0.5, -0.3, 0.8).normalize().as_vec3();
+        let samples = 100_000;
+        let mean_cosine = (0..samples)
+            .map(|_| ggx.sample_visible_normal(wo).dot(wo))
+            .sum::<f32>()
+            / samples as f32;
+
+        // E[wo·m] = ∫ g1 (wo·m)² D(m) / wo.z dm, estimated by uniform sampling
+        let expected = (0..samples)
+            .map(|_| {
+                let mut m = UnitVec3::random().as_vec3();
+                m.z = m.z.abs();
+                ggx.visible_normal_pdf(wo, m) * wo.dot(m) * 2. * PI
+            })
+            .sum::<f32>()
+            / samples as f32;
+        assert!((mean_cosine - expected).abs() < 0.02);
+    }
+
+    #[test]
+    fn fresnel_at_normal_incidence() {
+        let (eta, k) = (0.2, 3.9);
+        let expected = ((eta - 1.) * (eta - 1.) + k * k) / ((eta + 1.) * (eta + 1.) + k * k);
+        assert!(approx_equals(conductor_fresnel(1., eta, k), expected));
+        assert!(approx_equals(conductor_fresnel(0., eta, k), 1.));
+
+        let mirror = Conductor::from_reflectance(Color::new(0.8, 0.5, 0.1), 0.);
+        let reflectance = mirror.fresnel(1.);
+        assert!(approx_equals(reflectance.r, 0.8));
+        assert!(approx_equals(reflectance.g, 0.5));
+        assert!(approx_equals(reflectance.b, 0.1));
+    }
+
+    #[test]
+    fn total_internal_reflection() {
+        // Beyond the critical angle, glass seen from inside reflects everything
+        let eta: f32 = 1. / 1.5;
+        let critical = eta.asin();
+        assert_eq!(dielectric_fresnel((critical + 0.01).cos(), eta), 1.);
+        assert!(dielectric_fresnel((critical - 0.01).cos(), eta) < 1.);
+        assert!(approx_equals(dielectric_fresnel(1., 1.5), 0.04));
+
+        let wo = Vec3::new((critical + 0.01).sin(), 0., (critical + 0.01).cos());
+        assert!(refract(wo, Vec3::new(0., 0., 1.), eta).is_none());
+    }
+
+    #[test]
+    fn rough_dielectric_samples_match_eval() {
+        let glass = RoughDielectric::new(1.5, 0.5);
+        let normal = Vec3::new(0., 1., 0.).normalize();
+        let point = Point::new(0., 0., 0.);
+        for ray in [
+            Ray::new(Point::new(-1., 1., 0.), Vec3::new(1., -1., 0.)),
+            Ray::new(Point::new(-1., -1., 0.), Vec3::new(1., 1., 0.)),
+        ] {
+            let hit = Hit::new(&ray, point, normal, 1., &glass, 0., 0.);
+            let (mut reflected, mut transmitted) = (false, false);
+            for _ in 0..200 {
+                let Some((scattered, weight)) = glass.scatter(&ray, &hit) else {
+                    continue;
+                };
+                let direction = scattered.direction.normalize();
+                let pdf = glass.pdf(&ray, &hit, direction);
+                let value = glass.eval(&ray, &hit, direction).unwrap();
+                assert!((value.r / pdf - weight.r).abs() < 1e-3 * weight.r.max(1.));
+
+                match direction.as_vec3().dot(hit.normal.as_vec3()) < 0. {
+                    true => transmitted = true,
+                    false => reflected = true,
+                }
+            }
+            assert!(reflected && transmitted);
+        }
+    }
+}
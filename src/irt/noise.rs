@@ -3,7 +3,7 @@ use std::array;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
-use super::{lerp, Point};
+use super::{lerp, Point, Vec3};
 
 /// Implementation of improved Perlin noise.
 /// Implemented with the help of these resources:
@@ -19,15 +19,7 @@ pub struct Perlin {
 }
 impl Perlin {
     pub fn new() -> Self {
-        let mut rng = thread_rng();
-
-        let mut permutation = [0; 512];
-        let (lower, upper) = permutation.split_at_mut(256);
-        lower.copy_from_slice(&array::from_fn::<usize, 256, _>(|i| i));
-        lower.shuffle(&mut rng);
-        upper.copy_from_slice(lower);
-
-        return Self { p: permutation };
+        return Self { p: permutation() };
     }
 
     pub fn noise(&self, point: Point) -> f32 {
@@ -107,3 +99,168 @@ impl Perlin {
         return lerp(c0, c1, z);
     }
 }
+
+/// Returns all integers from 0..=255 in a random order, repeated once,
+/// which hashes lattice coordinates when indexed by each coordinate in turn.
+fn permutation() -> [usize; 512] {
+    let mut rng = thread_rng();
+
+    let mut permutation = [0; 512];
+    let (lower, upper) = permutation.split_at_mut(256);
+    lower.copy_from_slice(&array::from_fn::<usize, 256, _>(|i| i));
+    lower.shuffle(&mut rng);
+    upper.copy_from_slice(lower);
+
+    return permutation;
+}
+
+/// Fractal Brownian motion: a sum of octaves of Perlin noise, each `lacunarity` times
+/// finer and `gain` times fainter than the previous one.
+#[derive(Debug)]
+pub struct Fbm {
+    perlin: Perlin,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+}
+impl Fbm {
+    pub fn new() -> Self {
+        return Self {
+            perlin: Perlin::new(),
+            octaves: 5,
+            lacunarity: 2.,
+            gain: 0.5,
+        };
+    }
+
+    pub fn with_octaves(self, octaves: u32) -> Self {
+        return Self { octaves, ..self };
+    }
+
+    pub fn with_lacunarity(self, lacunarity: f32) -> Self {
+        return Self { lacunarity, ..self };
+    }
+
+    pub fn with_gain(self, gain: f32) -> Self {
+        return Self { gain, ..self };
+    }
+
+    /// Returns the sum of `shape` applied to each octave at `point`,
+    /// divided by the sum of the amplitudes.
+    fn octaves(&self, point: Point, shape: impl Fn(f32) -> f32) -> f32 {
+        let mut sum = 0.;
+        let mut total_amplitude = 0.;
+        let mut amplitude = 1.;
+        let mut frequency = 1.;
+        for _ in 0..self.octaves {
+            sum += amplitude * shape(self.perlin.noise(frequency * point));
+            total_amplitude += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        if total_amplitude == 0. {
+            return 0.;
+        }
+        return sum / total_amplitude;
+    }
+
+    /// Returns the noise at `point`, from -1 to 1.
+    pub fn noise(&self, point: Point) -> f32 {
+        return self.octaves(point, |noise| noise);
+    }
+
+    /// Returns the sum of the absolute values of the octaves at `point`, from 0 to 1,
+    /// which creases where the octaves cross zero.
+    pub fn turbulence(&self, point: Point) -> f32 {
+        return self.octaves(point, f32::abs);
+    }
+}
+
+/// Worley noise, also known as cellular or Voronoi noise: the distances from a point to the
+/// closest of a set of feature points scattered one per cell of the integer lattice.
+#[derive(Debug)]
+pub struct Worley {
+    /// The permutation table, as for [`Perlin`].
+    p: [usize; 512],
+}
+impl Worley {
+    pub fn new() -> Self {
+        return Self { p: permutation() };
+    }
+
+    /// Returns the feature point of the lattice cell whose lowest corner is `cell`.
+    fn feature_point(&self, cell: [i32; 3]) -> Point {
+        let [x, y, z] = cell.map(|coordinate| (coordinate & 255) as usize);
+        let hash = self.p[self.p[self.p[x] + y] + z];
+        // Further rounds of the table give independent offsets along each axis
+        let offset_x = self.p[hash] as f32 / 256.;
+        let offset_y = self.p[self.p[hash] + 1] as f32 / 256.;
+        let offset_z = self.p[self.p[self.p[hash] + 1] + 2] as f32 / 256.;
+        return Point::new(
+            cell[0] as f32 + offset_x,
+            cell[1] as f32 + offset_y,
+            cell[2] as f32 + offset_z,
+        );
+    }
+
+    /// Returns the distances from `point` to the closest and the second closest feature points.
+    pub fn distances(&self, point: Point) -> (f32, f32) {
+        let cell = [point.x, point.y, point.z].map(|coordinate| coordinate.floor() as i32);
+        let mut closest = f32::INFINITY;
+        let mut second = f32::INFINITY;
+        // The closest feature points are always in the neighbouring cells
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbour = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                    let offset: Vec3 = self.feature_point(neighbour) - point;
+                    let distance = offset.length();
+                    if distance < closest {
+                        second = closest;
+                        closest = distance;
+                    } else if distance < second {
+                        second = distance;
+                    }
+                }
+            }
+        }
+        return (closest, second);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::approx_equals;
+
+    #[test]
+    fn fbm_sums_octaves() {
+        let single = Fbm::new().with_octaves(1);
+        let point = Point::new(0.3, 1.7, -2.2);
+        assert!(approx_equals(
+            single.noise(point),
+            single.perlin.noise(point)
+        ));
+
+        let fbm = Fbm::new();
+        for i in 0..100 {
+            let point = Point::new(0.37 * i as f32, 0.11 * i as f32, -0.23 * i as f32);
+            assert!((-1. ..=1.).contains(&fbm.noise(point)));
+            assert!((0. ..=1.).contains(&fbm.turbulence(point)));
+        }
+    }
+
+    #[test]
+    fn worley_distances_are_ordered_and_vanish_at_feature_points() {
+        let worley = Worley::new();
+        for i in 0..100 {
+            let point = Point::new(0.37 * i as f32, 0.11 * i as f32, -0.23 * i as f32);
+            let (closest, second) = worley.distances(point);
+            assert!(closest <= second);
+            assert!(closest <= 3_f32.sqrt());
+        }
+
+        let feature_point = worley.feature_point([4, -2, 7]);
+        assert_eq!(worley.distances(feature_point).0, 0.);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::irt::{UnitVec3, Vec3};
+
+/// An orthonormal basis, used to express directions relative to a normal or a ray direction.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+impl Onb {
+    /// Builds a basis whose `w` axis is `w`.
+    ///
+    /// Uses the branchless construction of
+    /// <https://graphics.pixar.com/library/OrthonormalB/paper.pdf>
+    pub fn new(w: UnitVec3) -> Self {
+        let w = w.as_vec3();
+        let sign = 1_f32.copysign(w.z);
+        let a = -1. / (sign + w.z);
+        let b = w.x * w.y * a;
+
+        return Self {
+            u: Vec3::new(1. + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            v: Vec3::new(b, sign + w.y * w.y * a, -w.y),
+            w,
+        };
+    }
+
+    /// Builds a basis whose `w` axis is `w` and whose `u` axis follows `tangent`,
+    /// made orthogonal to `w`. Falls back to [`Onb::new`] if `tangent` is parallel to `w`.
+    pub fn from_tangent(w: UnitVec3, tangent: Vec3) -> Self {
+        let w = w.as_vec3();
+        let u = tangent - tangent.dot(w) * w;
+        if u.length_squared() < 1e-12 {
+            return Self::new(w.normalize());
+        }
+
+        let u = u.normalize().as_vec3();
+        return Self {
+            u,
+            v: w.cross(u),
+            w,
+        };
+    }
+
+    /// Converts a vector from coordinates in this basis to world coordinates.
+    pub fn local(&self, vec3: Vec3) -> Vec3 {
+        return vec3.x * self.u + vec3.y * self.v + vec3.z * self.w;
+    }
+
+    /// Converts a vector from world coordinates to coordinates in this basis.
+    pub fn world_to_local(&self, vec3: Vec3) -> Vec3 {
+        return Vec3::new(vec3.dot(self.u), vec3.dot(self.v), vec3.dot(self.w));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::approx_equals;
+
+    #[test]
+    fn basis_is_orthonormal() {
+        for w in [
+            Vec3::new(0., 0., 1.),
+            Vec3::new(0., 0., -1.),
+            Vec3::new(1., 2., 3.),
+            Vec3::new(-3., 0.5, -0.1),
+        ] {
+            let onb = Onb::new(w.normalize());
+            for axis in [onb.u, onb.v, onb.w] {
+                assert!(approx_equals(axis.length(), 1.));
+            }
+            assert!(approx_equals(onb.u.dot(onb.v), 0.));
+            assert!(approx_equals(onb.u.dot(onb.w), 0.));
+            assert!(approx_equals(onb.v.dot(onb.w), 0.));
+            assert_eq!(onb.local(Vec3::new(0., 0., 1.)), onb.w);
+            let vector = Vec3::new(0.3, -2., 1.);
+            assert_eq!(onb.local(onb.world_to_local(vector)), vector);
+        }
+    }
+
+    #[test]
+    fn tangent_basis_follows_tangent() {
+        let onb = Onb::from_tangent(Vec3::new(0., 1., 0.).normalize(), Vec3::new(2., 1., 0.));
+        assert_eq!(onb.u, Vec3::new(1., 0., 0.));
+        assert_eq!(onb.v, Vec3::new(0., 0., -1.));
+
+        // A tangent along the normal is ignored
+        let onb = Onb::from_tangent(Vec3::new(0., 1., 0.).normalize(), Vec3::new(0., 3., 0.));
+        assert!(approx_equals(onb.u.length(), 1.));
+        assert!(approx_equals(onb.u.dot(onb.w), 0.));
+    }
+}
@@ -58,7 +58,7 @@ impl Add for Point {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
-        return Self::new(self.x + rhs.x, self.y + rhs.y, self.z - rhs.z);
+        return Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z);
     }
 }
 impl Add<Vec3> for Point {
@@ -0,0 +1,424 @@
+use std::f32::consts::PI;
+
+use rand::random;
+
+use crate::irt::{
+    dielectric_fresnel, half_vector, lerp, refract, Color, Ggx, Hit, Material, Onb, Ray, Texture,
+    UnitVec3, Vec3,
+};
+
+/// The parameters of a [`Principled`] material at a point of its surface.
+struct Parameters {
+    base_color: Color,
+    metallic: f32,
+    roughness: f32,
+    specular: f32,
+    clearcoat: f32,
+    sheen: f32,
+    transmission: f32,
+    subsurface: f32,
+    /// The ratio of the refractive index behind the surface to the one in front.
+    eta: f32,
+}
+
+/// A principled material in the manner of the Disney BSDF, combining a diffuse base,
+/// a specular layer, a clearcoat, sheen and transmission under a few intuitive parameters.
+/// <https://blog.selfshadow.com/publications/s2015-shading-course/burley/s2015_pbs_disney_bsdf_notes.pdf>
+///
+/// Every parameter is given by a texture, so it can vary over the surface.
+/// Scalar parameters, from 0 to 1, read the red channel of their texture.
+/// - `metallic` blends from a dielectric to a conductor tinted by the base color.
+/// - `specular` sets the reflectance of the dielectric along the normal, 0.5 being 4%,
+///   and the refractive index of transmission with it.
+/// - `subsurface` flattens the diffuse lobe as light scattered beneath the surface would.
+/// - `sheen` adds the soft grazing reflection of cloth.
+/// - `clearcoat` adds a second, colorless specular layer, such as varnish.
+/// - `transmission` blends the diffuse base into rough refraction, such as glass.
+#[derive(Debug)]
+pub struct Principled {
+    base_color: Box<dyn Texture>,
+    metallic: Box<dyn Texture>,
+    roughness: Box<dyn Texture>,
+    specular: Box<dyn Texture>,
+    clearcoat: Box<dyn Texture>,
+    clearcoat_roughness: f32,
+    sheen: Box<dyn Texture>,
+    transmission: Box<dyn Texture>,
+    subsurface: Box<dyn Texture>,
+}
+impl Principled {
+    /// Below this roughness, highlights are too sharp to sample reliably.
+    const MIN_ROUGHNESS: f32 = 0.05;
+
+    /// Creates a rough dielectric with the given base color.
+    pub fn new(base_color: Box<dyn Texture>) -> Self {
+        return Self {
+            base_color,
+            metallic: Box::new(0.),
+            roughness: Box::new(0.5),
+            specular: Box::new(0.5),
+            clearcoat: Box::new(0.),
+            clearcoat_roughness: 0.1,
+            sheen: Box::new(0.),
+            transmission: Box::new(0.),
+            subsurface: Box::new(0.),
+        };
+    }
+
+    pub fn with_metallic(self, metallic: impl Texture + 'static) -> Self {
+        return Self {
+            metallic: Box::new(metallic),
+            ..self
+        };
+    }
+
+    pub fn with_roughness(self, roughness: impl Texture + 'static) -> Self {
+        return Self {
+            roughness: Box::new(roughness),
+            ..self
+        };
+    }
+
+    pub fn with_specular(self, specular: impl Texture + 'static) -> Self {
+        return Self {
+            specular: Box::new(specular),
+            ..self
+        };
+    }
+
+    /// Adds a clearcoat of the given amount, whose roughness is uniform over the surface.
+    pub fn with_clearcoat(self, clearcoat: impl Texture + 'static, roughness: f32) -> Self {
+        return Self {
+            clearcoat: Box::new(clearcoat),
+            clearcoat_roughness: roughness,
+            ..self
+        };
+    }
+
+    pub fn with_sheen(self, sheen: impl Texture + 'static) -> Self {
+        return Self {
+            sheen: Box::new(sheen),
+            ..self
+        };
+    }
+
+    pub fn with_transmission(self, transmission: impl Texture + 'static) -> Self {
+        return Self {
+            transmission: Box::new(transmission),
+            ..self
+        };
+    }
+
+    pub fn with_subsurface(self, subsurface: impl Texture + 'static) -> Self {
+        return Self {
+            subsurface: Box::new(subsurface),
+            ..self
+        };
+    }
+
+    fn parameters(&self, hit: &Hit) -> Parameters {
+        let scalar = |texture: &dyn Texture| texture.value_at(hit).r.clamp(0., 1.);
+        let specular = scalar(self.specular.as_ref());
+
+        // Reflectance along the normal, from which the refractive index follows
+        let sqrt_f0 = (0.08 * specular).sqrt().min(0.99);
+        let refraction_index = (1. + sqrt_f0) / (1. - sqrt_f0);
+
+        return Parameters {
+            base_color: self.base_color.value_at(hit),
+            metallic: scalar(self.metallic.as_ref()),
+            roughness: scalar(self.roughness.as_ref()).max(Self::MIN_ROUGHNESS),
+            specular,
+            clearcoat: scalar(self.clearcoat.as_ref()),
+            sheen: scalar(self.sheen.as_ref()),
+            transmission: scalar(self.transmission.as_ref()),
+            subsurface: scalar(self.subsurface.as_ref()),
+            eta: match hit.front_face {
+                true => refraction_index,
+                false => 1. / refraction_index,
+            },
+        };
+    }
+
+    fn clearcoat_distribution(&self) -> Ggx {
+        return Ggx::new(self.clearcoat_roughness.max(Self::MIN_ROUGHNESS), 0.);
+    }
+
+    /// The probabilities of sampling the diffuse, specular, transmission and clearcoat
+    /// lobes, roughly in proportion to the light they reflect towards `wo`.
+    fn lobe_probabilities(&self, p: &Parameters, wo: Vec3) -> [f32; 4] {
+        let fresnel = match p.specular > 0. {
+            true => dielectric_fresnel(wo.z, p.eta),
+            false => 0.,
+        };
+        let dielectric = 1. - p.metallic;
+        let base = Self::clearcoat_attenuation(p, wo);
+        let weights = [
+            base * dielectric * (1. - p.transmission),
+            base * lerp(fresnel, 1., p.metallic).max(0.05),
+            base * dielectric * p.transmission * (1. - fresnel),
+            0.25 * p.clearcoat,
+        ];
+
+        let total: f32 = weights.iter().sum();
+        return weights.map(|weight| weight / total);
+    }
+
+    /// The fraction of light that gets through the clearcoat to the layers below,
+    /// and back, as in Burley's extension of the Disney BRDF.
+    fn clearcoat_attenuation(p: &Parameters, wo: Vec3) -> f32 {
+        return 1. - 0.25 * p.clearcoat * lerp(0.04, 1., schlick_weight(wo.z));
+    }
+
+    /// The BSDF times the cosine, in the local frame of the surface.
+    fn eval_local(&self, p: &Parameters, wo: Vec3, wi: Vec3) -> Color {
+        let specular = Ggx::new(p.roughness, 0.);
+        let dielectric = 1. - p.metallic;
+        let base = Self::clearcoat_attenuation(p, wo);
+
+        if wi.z < 0. {
+            if dielectric * p.transmission <= 0. || p.specular <= 0. {
+                return Color::black();
+            }
+
+            let (m, _) = half_vector(wo, wi, p.eta);
+            let (cos_o, cos_i) = (wo.dot(m), wi.dot(m));
+            if cos_o <= 0. || cos_i >= 0. {
+                return Color::black();
+            }
+
+            let denominator = cos_o + p.eta * cos_i;
+            let value = (1. - dielectric_fresnel(cos_o, p.eta))
+                * specular.d(m)
+                * specular.g(wo, wi)
+                * p.eta
+                * p.eta
+                * cos_o
+                * -cos_i
+                / (wo.z * denominator * denominator);
+            return p.base_color * (base * dielectric * p.transmission * value);
+        }
+
+        let m = (wo + wi).normalize().as_vec3();
+        let cos_d = wi.dot(m);
+        let mut value = Color::black();
+
+        // Diffuse, with retro-reflection at grazing angles on rough surfaces,
+        // blended into the flatter response of subsurface scattering
+        let (fl, fv, fd) = (
+            schlick_weight(wi.z),
+            schlick_weight(wo.z),
+            schlick_weight(cos_d),
+        );
+        let fd90 = 0.5 + 2. * p.roughness * cos_d * cos_d;
+        let retro_reflection = lerp(1., fd90, fl) * lerp(1., fd90, fv);
+        let fss90 = p.roughness * cos_d * cos_d;
+        let fss = lerp(1., fss90, fl) * lerp(1., fss90, fv);
+        let subsurface = 1.25 * (fss * (1. / (wi.z + wo.z) - 0.5) + 0.5);
+        let diffuse = p.base_color * (lerp(retro_reflection, subsurface, p.subsurface) / PI);
+
+        let luminance = p.base_color.luminance();
+        let tint = match luminance > 0. {
+            true => p.base_color * (1. / luminance),
+            false => Color::white(),
+        };
+        let sheen = (Color::white() * 0.5 + tint * 0.5) * (p.sheen * fd);
+        // Light reflected by the specular layer does not reach the diffuse base below it
+        let specular_reflectance = match p.specular > 0. {
+            true => dielectric_fresnel(wo.z, p.eta),
+            false => 0.,
+        };
+        value += (diffuse + sheen)
+            * ((1. - specular_reflectance) * dielectric * (1. - p.transmission) * wi.z);
+
+        // Specular reflection, from a dielectric or a conductor tinted by the base color
+        let cos_o = wo.dot(m);
+        let fresnel = Color::white() * (dielectric * dielectric_fresnel(cos_o, p.eta))
+            + schlick(p.base_color, cos_o) * p.metallic;
+        value += fresnel * (specular.d(m) * specular.g(wo, wi) / (4. * wo.z));
+        value *= base;
+
+        if p.clearcoat > 0. {
+            let clearcoat = self.clearcoat_distribution();
+            let fresnel = lerp(0.04, 1., schlick_weight(cos_o));
+            let reflected = fresnel * clearcoat.d(m) * clearcoat.g(wo, wi) / (4. * wo.z);
+            value += Color::white() * (0.25 * p.clearcoat * reflected);
+        }
+
+        return value;
+    }
+
+    /// The density of [`Principled::sample_local`] sampling `wi`.
+    fn pdf_local(&self, p: &Parameters, wo: Vec3, wi: Vec3) -> f32 {
+        let [diffuse, reflection, transmission, clearcoat] = self.lobe_probabilities(p, wo);
+        let specular = Ggx::new(p.roughness, 0.);
+
+        if wi.z < 0. {
+            let (m, _) = half_vector(wo, wi, p.eta);
+            let (cos_o, cos_i) = (wo.dot(m), wi.dot(m));
+            if transmission <= 0. || cos_o <= 0. || cos_i >= 0. {
+                return 0.;
+            }
+
+            let denominator = cos_o + p.eta * cos_i;
+            return transmission * specular.visible_normal_pdf(wo, m) * p.eta * p.eta * -cos_i
+                / (denominator * denominator);
+        }
+
+        let m = (wo + wi).normalize().as_vec3();
+        let cos_o = wo.dot(m);
+        let mut pdf = diffuse * wi.z / PI;
+        if cos_o > 0. {
+            pdf += reflection * specular.visible_normal_pdf(wo, m) / (4. * cos_o);
+            if clearcoat > 0. {
+                pdf += clearcoat * self.clearcoat_distribution().visible_normal_pdf(wo, m)
+                    / (4. * cos_o);
+            }
+        }
+        return pdf;
+    }
+
+    /// Samples a scattered direction by first choosing a lobe.
+    fn sample_local(&self, p: &Parameters, wo: Vec3) -> Option<Vec3> {
+        let [diffuse, reflection, transmission, _] = self.lobe_probabilities(p, wo);
+        let specular = Ggx::new(p.roughness, 0.);
+
+        let choice = random::<f32>();
+        if choice < diffuse {
+            let r = random::<f32>().sqrt();
+            let phi = 2. * PI * random::<f32>();
+            return Some(Vec3::new(
+                r * phi.cos(),
+                r * phi.sin(),
+                (1. - r * r).max(0.).sqrt(),
+            ));
+        }
+        if choice < diffuse + reflection {
+            let m = specular.sample_visible_normal(wo);
+            return Some(2. * wo.dot(m) * m - wo);
+        }
+        if choice < diffuse + reflection + transmission {
+            let m = specular.sample_visible_normal(wo);
+            return refract(wo, m, p.eta);
+        }
+        let m = self.clearcoat_distribution().sample_visible_normal(wo);
+        return Some(2. * wo.dot(m) * m - wo);
+    }
+
+    /// Returns the local frame of `hit` and the outgoing direction of `ray_in` in it.
+    fn local_frame(ray_in: &Ray, hit: &Hit) -> (Onb, Vec3) {
+        let onb = hit.frame();
+        let wo = onb.world_to_local(-ray_in.direction.normalize().as_vec3());
+        return (onb, wo);
+    }
+}
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit) -> Option<(Ray, Color)> {
+        let (onb, wo) = Self::local_frame(ray_in, hit);
+        if wo.z <= 0. {
+            return None;
+        }
+
+        let p = self.parameters(hit);
+        let wi = self.sample_local(&p, wo)?;
+        let pdf = self.pdf_local(&p, wo, wi);
+        if pdf <= 0. {
+            return None;
+        }
+
+        return Some((
+            Ray::new(hit.point, onb.local(wi)),
+            self.eval_local(&p, wo, wi) * (1. / pdf),
+        ));
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> Option<Color> {
+        let (onb, wo) = Self::local_frame(ray_in, hit);
+        let wi = onb.world_to_local(direction.as_vec3());
+        if wo.z <= 0. || wi.z == 0. {
+            return Some(Color::black());
+        }
+        return Some(self.eval_local(&self.parameters(hit), wo, wi));
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: UnitVec3) -> f32 {
+        let (onb, wo) = Self::local_frame(ray_in, hit);
+        let wi = onb.world_to_local(direction.as_vec3());
+        if wo.z <= 0. || wi.z == 0. {
+            return 0.;
+        }
+        return self.pdf_local(&self.parameters(hit), wo, wi);
+    }
+}
+
+/// The weight of Schlick's Fresnel approximation, `(1 - cos θ)⁵`.
+fn schlick_weight(cos_theta: f32) -> f32 {
+    return (1. - cos_theta).clamp(0., 1.).powi(5);
+}
+
+/// Schlick's Fresnel approximation for a reflectance `f0` along the normal.
+fn schlick(f0: Color, cos_theta: f32) -> Color {
+    let weight = schlick_weight(cos_theta);
+    return f0 * (1. - weight) + Color::white() * weight;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::Point;
+
+    /// Returns the mean weight of light scattered by `material` for a ray arriving
+    /// at `angle` degrees from the normal.
+    fn albedo(material: &Principled, angle: f32) -> Color {
+        let angle = angle.to_radians();
+        let ray = Ray::new(
+            Point::new(-angle.sin(), angle.cos(), 0.),
+            Vec3::new(angle.sin(), -angle.cos(), 0.),
+        );
+        let normal = Vec3::new(0., 1., 0.).normalize();
+        let hit = Hit::new(&ray, Point::new(0., 0., 0.), normal, 1., material, 0., 0.);
+
+        let samples = 20_000;
+        let mut total = Color::black();
+        for _ in 0..samples {
+            if let Some((_, weight)) = material.scatter(&ray, &hit) {
+                total += weight;
+            }
+        }
+        return total * (1. / samples as f32);
+    }
+
+    #[test]
+    fn conserves_energy() {
+        let materials = [
+            Principled::new(Box::new(Color::white())).with_roughness(0.2),
+            Principled::new(Box::new(Color::white())).with_metallic(1.),
+            Principled::new(Box::new(Color::white()))
+                .with_transmission(1.)
+                .with_roughness(0.3),
+            Principled::new(Box::new(Color::white()))
+                .with_roughness(0.2)
+                .with_clearcoat(1., 0.1),
+        ];
+        // Like Disney's, the sheen, the subsurface approximation and the retro-reflection
+        // of surfaces rougher than 0.25 trade energy conservation for artistic control,
+        // so they are left out
+        for material in &materials {
+            for angle in [0., 30., 60.] {
+                let albedo = albedo(material, angle);
+                assert!(albedo.g <= 1., "{material:?} at {angle}°: {albedo:?}");
+                assert!(albedo.g > 0.5, "{material:?} at {angle}°: {albedo:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn metals_reflect_their_base_color() {
+        let gold = Principled::new(Box::new(Color::new(1., 0.8, 0.3)))
+            .with_metallic(1.)
+            .with_roughness(0.2);
+        let albedo = albedo(&gold, 0.);
+        assert!((albedo.r - 1.).abs() < 0.1);
+        assert!((albedo.b - 0.3).abs() < 0.1);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::irt::{Color, Fbm, Point, Texture, Vec3, Worley};
+
+/// Maps values from 0 to 1 to colors, interpolating linearly between color stops.
+/// Values beyond the first or the last stop take the color of that stop.
+#[derive(Debug, Clone)]
+pub struct ColorRamp {
+    /// The positions and colors of the stops, by increasing position.
+    stops: Vec<(f32, Color)>,
+}
+impl ColorRamp {
+    pub fn new(mut stops: Vec<(f32, Color)>) -> Self {
+        stops.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        return Self { stops };
+    }
+
+    /// A ramp from `start` at 0 to `end` at 1.
+    pub fn between(start: Color, end: Color) -> Self {
+        return Self::new(vec![(0., start), (1., end)]);
+    }
+
+    pub fn color(&self, value: f32) -> Color {
+        let upper = self
+            .stops
+            .partition_point(|&(position, _)| position < value);
+        if upper == 0 {
+            return self
+                .stops
+                .first()
+                .map_or(Color::black(), |&(_, color)| color);
+        }
+        if upper == self.stops.len() {
+            return self.stops[upper - 1].1;
+        }
+
+        let (p0, c0) = self.stops[upper - 1];
+        let (p1, c1) = self.stops[upper];
+        let t = (value - p0) / (p1 - p0);
+        return c0 * (1. - t) + c1 * t;
+    }
+}
+
+/// Fractal noise, from the first color of its ramp where the noise is -1 to the last where it is 1.
+#[derive(Debug)]
+pub struct FbmTexture {
+    fbm: Fbm,
+    scale: f32,
+    ramp: ColorRamp,
+}
+impl FbmTexture {
+    pub fn new(scale: f32) -> Self {
+        return Self {
+            fbm: Fbm::new(),
+            scale,
+            ramp: ColorRamp::between(Color::black(), Color::white()),
+        };
+    }
+
+    pub fn with_fbm(self, fbm: Fbm) -> Self {
+        return Self { fbm, ..self };
+    }
+
+    pub fn with_ramp(self, ramp: ColorRamp) -> Self {
+        return Self { ramp, ..self };
+    }
+}
+impl Texture for FbmTexture {
+    fn value(&self, _u: f32, _v: f32, point: Point) -> Color {
+        let noise = self.fbm.noise(self.scale * point);
+        return self.ramp.color(0.5 * (1. + noise));
+    }
+}
+
+/// Turbulence: fractal noise of absolute values, with sharp creases, such as for smoke or fire.
+#[derive(Debug)]
+pub struct TurbulenceTexture {
+    fbm: Fbm,
+    scale: f32,
+    ramp: ColorRamp,
+}
+impl TurbulenceTexture {
+    pub fn new(scale: f32) -> Self {
+        return Self {
+            fbm: Fbm::new(),
+            scale,
+            ramp: ColorRamp::between(Color::black(), Color::white()),
+        };
+    }
+
+    pub fn with_fbm(self, fbm: Fbm) -> Self {
+        return Self { fbm, ..self };
+    }
+
+    pub fn with_ramp(self, ramp: ColorRamp) -> Self {
+        return Self { ramp, ..self };
+    }
+}
+impl Texture for TurbulenceTexture {
+    fn value(&self, _u: f32, _v: f32, point: Point) -> Color {
+        return self.ramp.color(self.fbm.turbulence(self.scale * point));
+    }
+}
+
+/// Marble: veins running across `x`, in the last color of the ramp, warped by turbulence.
+#[derive(Debug)]
+pub struct MarbleTexture {
+    fbm: Fbm,
+    scale: f32,
+    /// The phase shift of the veins at full turbulence, in radians.
+    distortion: f32,
+    ramp: ColorRamp,
+}
+impl MarbleTexture {
+    pub fn new(scale: f32) -> Self {
+        return Self {
+            fbm: Fbm::new(),
+            scale,
+            distortion: 10.,
+            ramp: ColorRamp::between(Color::white(), Color::new(0.3, 0.3, 0.35)),
+        };
+    }
+
+    pub fn with_fbm(self, fbm: Fbm) -> Self {
+        return Self { fbm, ..self };
+    }
+
+    pub fn with_distortion(self, distortion: f32) -> Self {
+        return Self { distortion, ..self };
+    }
+
+    pub fn with_ramp(self, ramp: ColorRamp) -> Self {
+        return Self { ramp, ..self };
+    }
+}
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f32, _v: f32, point: Point) -> Color {
+        let point = self.scale * point;
+        let phase = point.x + self.distortion * self.fbm.turbulence(point);
+        // Veins are narrow, where the sine peaks
+        let vein = (0.5 * (1. + phase.sin())).powi(4);
+        return self.ramp.color(vein);
+    }
+}
+
+/// Wood: growth rings around the `y` axis, `1 / scale` apart, wobbled by noise.
+/// Each ring goes through the ramp from 0 to 1.
+#[derive(Debug)]
+pub struct WoodTexture {
+    fbm: Fbm,
+    scale: f32,
+    /// The largest displacement of the rings, in rings.
+    distortion: f32,
+    ramp: ColorRamp,
+}
+impl WoodTexture {
+    pub fn new(scale: f32) -> Self {
+        return Self {
+            fbm: Fbm::new(),
+            scale,
+            distortion: 0.5,
+            ramp: ColorRamp::between(Color::new(0.55, 0.35, 0.18), Color::new(0.35, 0.2, 0.08)),
+        };
+    }
+
+    pub fn with_fbm(self, fbm: Fbm) -> Self {
+        return Self { fbm, ..self };
+    }
+
+    pub fn with_distortion(self, distortion: f32) -> Self {
+        return Self { distortion, ..self };
+    }
+
+    pub fn with_ramp(self, ramp: ColorRamp) -> Self {
+        return Self { ramp, ..self };
+    }
+}
+impl Texture for WoodTexture {
+    fn value(&self, _u: f32, _v: f32, point: Point) -> Color {
+        let point = self.scale * point;
+        let radius = (point.x * point.x + point.z * point.z).sqrt();
+        let rings = radius + self.distortion * self.fbm.noise(point);
+        return self.ramp.color(rings - rings.floor());
+    }
+}
+
+/// What a [`VoronoiTexture`] shows of its cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoronoiPattern {
+    /// The distance to the closest feature point, 0 at the center of each cell.
+    Distance,
+    /// The difference of the distances to the two closest feature points,
+    /// 0 on the edges between cells.
+    Edges,
+}
+
+/// Worley noise, mapping its distances through the ramp, such as for scales,
+/// cracked mud or cells.
+#[derive(Debug)]
+pub struct VoronoiTexture {
+    worley: Worley,
+    scale: f32,
+    pattern: VoronoiPattern,
+    ramp: ColorRamp,
+}
+impl VoronoiTexture {
+    pub fn new(scale: f32) -> Self {
+        return Self {
+            worley: Worley::new(),
+            scale,
+            pattern: VoronoiPattern::Distance,
+            ramp: ColorRamp::between(Color::black(), Color::white()),
+        };
+    }
+
+    pub fn with_pattern(self, pattern: VoronoiPattern) -> Self {
+        return Self { pattern, ..self };
+    }
+
+    pub fn with_ramp(self, ramp: ColorRamp) -> Self {
+        return Self { ramp, ..self };
+    }
+}
+impl Texture for VoronoiTexture {
+    fn value(&self, _u: f32, _v: f32, point: Point) -> Color {
+        let (closest, second) = self.worley.distances(self.scale * point);
+        let value = match self.pattern {
+            VoronoiPattern::Distance => closest,
+            VoronoiPattern::Edges => second - closest,
+        };
+        return self.ramp.color(value);
+    }
+}
+
+/// Warps the domain of a solid texture by fractal noise, displacing the points at which it
+/// is evaluated by up to `strength`, which turns regular patterns into organic ones.
+#[derive(Debug)]
+pub struct DomainWarp {
+    texture: Box<dyn Texture>,
+    fbm: Fbm,
+    scale: f32,
+    strength: f32,
+}
+impl DomainWarp {
+    pub fn new(texture: Box<dyn Texture>, scale: f32, strength: f32) -> Self {
+        return Self {
+            texture,
+            fbm: Fbm::new(),
+            scale,
+            strength,
+        };
+    }
+
+    pub fn with_fbm(self, fbm: Fbm) -> Self {
+        return Self { fbm, ..self };
+    }
+}
+impl Texture for DomainWarp {
+    fn value(&self, u: f32, v: f32, point: Point) -> Color {
+        // Distant samples of the same noise give uncorrelated displacements along each axis
+        let point_scaled = self.scale * point;
+        let displacement = Vec3::new(
+            self.fbm.noise(point_scaled),
+            self.fbm.noise(point_scaled + Vec3::new(5.2, 1.3, 2.8)),
+            self.fbm.noise(point_scaled + Vec3::new(1.7, 9.2, 4.1)),
+        );
+        return self
+            .texture
+            .value(u, v, point + self.strength * displacement);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::approx_equals;
+
+    #[test]
+    fn ramp_interpolates_between_stops() {
+        let ramp = ColorRamp::new(vec![
+            (1., Color::white()),
+            (0.5, Color::new(1., 0., 0.)),
+            (0., Color::black()),
+        ]);
+        assert!(approx_equals(ramp.color(0.25).r, 0.5));
+        assert!(approx_equals(ramp.color(0.75).g, 0.5));
+        assert_eq!(ramp.color(-1.).r, 0.);
+        assert_eq!(ramp.color(2.).b, 1.);
+    }
+
+    #[test]
+    fn undistorted_wood_rings_repeat_with_radius() {
+        let wood = WoodTexture::new(2.)
+            .with_distortion(0.)
+            .with_ramp(ColorRamp::between(Color::black(), Color::white()));
+        let grey = |x: f32, z: f32| wood.value(0., 0., Point::new(x, 0.3, z)).r;
+        assert!(approx_equals(grey(0.1, 0.), 0.2));
+        assert!(approx_equals(grey(0.6, 0.), 0.2));
+        assert!(approx_equals(grey(0., -1.1), 0.2));
+    }
+}
@@ -1,4 +1,8 @@
-use crate::irt::{Aabb, Hit, Hittable, Interval, Material, Point, Ray, UnitVec3, Vec3};
+use std::sync::Arc;
+
+use crate::irt::{
+    is_masked, Aabb, Hit, Hittable, Interval, Material, Point, Ray, Texture, UnitVec3, Vec3,
+};
 
 /// A quadrilateral (techinally a parallellogram).
 /// Defined by:
@@ -7,7 +11,7 @@ use crate::irt::{Aabb, Hit, Hittable, Interval, Material, Point, Ray, UnitVec3,
 /// 2. A [Vec3] `u` representing one side of the quad
 /// 3. A [Vec3] `v` representing the other, non-parallell side of the quad.
 #[derive(Debug)]
-pub struct Quad<'a> {
+pub struct Quad {
     /// The starting corner
     q: Point,
     /// A side of the quad
@@ -16,14 +20,15 @@ pub struct Quad<'a> {
     v: Vec3,
     /// Pre-calculated used to determine the planar coordinates of an intersection
     w: Vec3,
-    material: &'a dyn Material,
+    material: Arc<dyn Material>,
     bounds: Aabb,
     normal: UnitVec3,
     /// The `d` in the general equation of a plane, pre-calculated
     d: f32,
+    opacity: Option<Arc<dyn Texture>>,
 }
-impl<'a> Quad<'a> {
-    pub fn new(q: Point, u: Vec3, v: Vec3, material: &'a dyn Material) -> Self {
+impl Quad {
+    pub fn new(q: Point, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
         let bounds_diagonal1 = Aabb::new(q, q + u + v);
         let bounds_diagonal2 = Aabb::new(q + u, q + v);
         let bounds = bounds_diagonal1 + bounds_diagonal2;
@@ -42,11 +47,21 @@ impl<'a> Quad<'a> {
             bounds,
             normal,
             d,
+            opacity: None,
+        };
+    }
+
+    /// Cuts holes in the quad where `opacity` is below 1, such as the gaps between the
+    /// leaves of a foliage card. See [`is_masked`].
+    pub fn with_opacity(self, opacity: Arc<dyn Texture>) -> Self {
+        return Self {
+            opacity: Some(opacity),
+            ..self
         };
     }
 
     /// Returns a 1x1x1 cube with corners in (0, 0, 0) and (1, 1, 1).
-    pub fn cube(material: &'a dyn Material) -> Vec<Self> {
+    pub fn cube(material: Arc<dyn Material>) -> Vec<Self> {
         let min = Point::new(0., 0., 0.);
         let max = Point::new(1., 1., 1.);
 
@@ -55,11 +70,11 @@ impl<'a> Quad<'a> {
         let dz = Vec3::new(0., 0., max.z - min.z);
 
         return vec![
-            Quad::new(Point::new(min.x, min.y, max.z), dx, dy, material),
-            Quad::new(Point::new(max.x, min.y, max.z), -dz, dy, material),
-            Quad::new(Point::new(max.x, min.y, min.z), -dx, dy, material),
-            Quad::new(Point::new(min.x, min.y, min.z), dz, dy, material),
-            Quad::new(Point::new(min.x, max.y, max.z), dx, -dz, material),
+            Quad::new(Point::new(min.x, min.y, max.z), dx, dy, material.clone()),
+            Quad::new(Point::new(max.x, min.y, max.z), -dz, dy, material.clone()),
+            Quad::new(Point::new(max.x, min.y, min.z), -dx, dy, material.clone()),
+            Quad::new(Point::new(min.x, min.y, min.z), dz, dy, material.clone()),
+            Quad::new(Point::new(min.x, max.y, max.z), dx, -dz, material.clone()),
             Quad::new(Point::new(min.x, min.y, min.z), dx, dz, material),
         ];
     }
@@ -70,13 +85,10 @@ impl<'a> Quad<'a> {
         let unit_interval = Interval::new(0., 1.);
         return unit_interval.surrounds(a) && unit_interval.surrounds(b);
     }
-}
 
-impl Hittable for Quad<'_> {
-    fn bounds(&self) -> Aabb {
-        return self.bounds;
-    }
-    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit> {
+    /// Returns the `t` of the intersection within `t_interval`, unless masked out by the
+    /// opacity texture, along with the planar coordinates `a` and `b` of the hit point.
+    fn intersect(&self, ray: &Ray, t_interval: &Interval) -> Option<(f32, f32, f32)> {
         let denominator = self.normal.as_vec3().dot(ray.direction);
 
         // Return `None` if the ray is parallell to the plane.
@@ -90,14 +102,32 @@ impl Hittable for Quad<'_> {
         }
 
         let point = ray.at(t);
-
         let relative_p = point - self.q;
         let a = self.w.dot(relative_p.cross(self.v));
         let b = self.w.dot(self.u.cross(relative_p));
-        if !self.is_interior(a, b) {
+        if !self.is_interior(a, b) || is_masked(self.opacity.as_deref(), a, b, point) {
             return None;
         }
 
-        return Some(Hit::new(ray, point, self.normal, t, self.material, a, b));
+        return Some((t, a, b));
+    }
+}
+
+impl Hittable for Quad {
+    fn bounds(&self) -> Aabb {
+        return self.bounds;
+    }
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        let (t, a, b) = self.intersect(ray, t_interval)?;
+        t_interval.max = t;
+
+        return Some(
+            Hit::new(ray, ray.at(t), self.normal, t, self.material.as_ref(), a, b)
+                .with_tangents(self.u, self.v),
+        );
+    }
+
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        return self.intersect(ray, t_interval).is_some();
     }
 }
@@ -4,11 +4,51 @@ use crate::irt::{Point, Vec3};
 pub struct Ray {
     pub origin: Point,
     pub direction: Vec3,
+    /// The kind of the ray, as a bit of a visibility mask.
+    /// Instances are only hit by rays whose mask shares a bit with their own.
+    pub mask: u32,
+    /// The width of the cone of directions the ray stands for, such as the pixel it
+    /// samples, at its origin. Filtered textures average over the cone where it is hit.
+    pub cone_width: f32,
+    /// The angle by which the ray cone widens per unit of distance along the ray.
+    pub cone_spread: f32,
 }
 
 impl Ray {
+    /// Mask bit of rays traced from the camera.
+    pub const CAMERA: u32 = 1 << 0;
+    /// Mask bit of rays scattered off a surface.
+    pub const REFLECTION: u32 = 1 << 1;
+    /// Mask bit of shadow rays, testing whether a point is occluded.
+    pub const SHADOW: u32 = 1 << 2;
+    /// Mask with all bits set, matching rays of any kind.
+    pub const ALL: u32 = u32::MAX;
+
     pub fn new(origin: Point, direction: Vec3) -> Self {
-        return Self { origin, direction };
+        return Self {
+            origin,
+            direction,
+            mask: Self::ALL,
+            cone_width: 0.,
+            cone_spread: 0.,
+        };
+    }
+
+    pub fn with_mask(self, mask: u32) -> Self {
+        return Self { mask, ..self };
+    }
+
+    pub fn with_cone(self, cone_width: f32, cone_spread: f32) -> Self {
+        return Self {
+            cone_width,
+            cone_spread,
+            ..self
+        };
+    }
+
+    /// Returns the width of the ray cone at `t`.
+    pub fn cone_width_at(&self, t: f32) -> f32 {
+        return self.cone_width + self.cone_spread * t * self.direction.length();
     }
 
     pub fn at(&self, t: f32) -> Point {
@@ -0,0 +1,166 @@
+use std::f32::consts::PI;
+
+use crate::irt::{Color, Degrees, DirectionalLight, Environment, UnitVec3, Vec3};
+
+/// The analytic daylight model of Preetham et al., "A Practical Analytic Model for Daylight".
+/// <https://courses.cs.duke.edu/cps124/fall01/resources/p91-preetham.pdf>
+///
+/// The sky is lit by a sun whose light is reddened by the atmosphere as it sets.
+/// Turbidity measures the haze of the atmosphere, from 2 for a clear sky to about 10.
+/// The model does not cover the ground, so directions below the horizon see the horizon.
+#[derive(Debug)]
+pub struct PreethamSky {
+    sun: DirectionalLight,
+    /// The angle between the sun and the zenith.
+    sun_theta: f32,
+    /// The luminance `Y` and chromaticity `x` and `y` of the zenith.
+    zenith: [f32; 3],
+    /// The coefficients `A` to `E` of the Perez distribution of `Y`, `x` and `y`.
+    perez: [[f32; 5]; 3],
+}
+impl PreethamSky {
+    /// Converts luminances of the model, in kcd/m², to the radiance units of the renderer.
+    const LUMINANCE_SCALE: f32 = 0.03;
+    /// The irradiance of the sun at the top of the atmosphere.
+    const SUN_IRRADIANCE: f32 = 2.5;
+    /// The angular radius of the sun seen from earth.
+    const SUN_ANGULAR_RADIUS: Degrees = Degrees(0.2665);
+
+    pub fn new(sun_direction: Vec3, turbidity: f32) -> Self {
+        let sun_direction = sun_direction.normalize();
+        let t = turbidity;
+        let sun_theta = sun_direction.as_vec3().y.clamp(0., 1.).acos();
+
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * sun_theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let theta = [sun_theta.powi(3), sun_theta.powi(2), sun_theta, 1.];
+        let zenith_chromaticity = |coefficients: [[f32; 4]; 3]| {
+            let [t2, t1, t0] = coefficients.map(|row| {
+                row.iter()
+                    .zip(theta)
+                    .map(|(coefficient, theta)| coefficient * theta)
+                    .sum::<f32>()
+            });
+            t * t * t2 + t * t1 + t0
+        };
+        let zenith_x = zenith_chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = zenith_chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        return Self {
+            sun: DirectionalLight::new(sun_direction.as_vec3(), Self::sun_irradiance(sun_theta, t))
+                .with_angular_radius(Self::SUN_ANGULAR_RADIUS),
+            sun_theta,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            perez,
+        };
+    }
+
+    /// Returns the irradiance of the sun at the ground, attenuated by Rayleigh scattering
+    /// and aerosols along its path through the atmosphere, at the red, green and blue
+    /// wavelengths of 680, 550 and 440 nm.
+    fn sun_irradiance(sun_theta: f32, turbidity: f32) -> Color {
+        // Relative air mass, from Kasten and Young
+        let elevation = 90. - sun_theta.to_degrees();
+        let air_mass = 1. / (sun_theta.cos() + 0.50572 * (elevation + 6.07995).powf(-1.6364));
+
+        let rayleigh = [0.040, 0.097, 0.240];
+        // Ångström's formula, with the coefficient derived from turbidity by Preetham et al.
+        let beta = 0.04608 * turbidity - 0.04586;
+        let aerosol = [0.68_f32, 0.55, 0.44].map(|wavelength| beta * wavelength.powf(-1.3));
+        let [r, g, b] = [0, 1, 2]
+            .map(|i| Self::SUN_IRRADIANCE * (-(rayleigh[i] + aerosol[i]) * air_mass).exp());
+
+        return Color::new(r, g, b);
+    }
+
+    /// The Perez sky luminance distribution, for a direction at `theta` from the zenith
+    /// and at `gamma` from the sun.
+    fn perez(coefficients: [f32; 5], theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = coefficients;
+        return (1. + a * (b / theta.cos()).exp())
+            * (1. + c * (d * gamma).exp() + e * gamma.cos().powi(2));
+    }
+}
+impl Environment for PreethamSky {
+    fn radiance(&self, direction: UnitVec3) -> Color {
+        let direction = direction.as_vec3();
+        let theta = direction.y.clamp(0.001, 1.).acos();
+        let gamma = direction
+            .dot(self.sun.direction.as_vec3())
+            .clamp(-1., 1.)
+            .acos();
+
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * Self::perez(self.perez[i], theta, gamma)
+                / Self::perez(self.perez[i], 0., self.sun_theta)
+        });
+
+        // From the xyY color space to CIE XYZ, then linear sRGB
+        let luminance = luminance * Self::LUMINANCE_SCALE;
+        let cie_x = x / y * luminance;
+        let cie_z = (1. - x - y) / y * luminance;
+        return Color::new(
+            (3.2406 * cie_x - 1.5372 * luminance - 0.4986 * cie_z).max(0.),
+            (-0.9689 * cie_x + 1.8758 * luminance + 0.0415 * cie_z).max(0.),
+            (0.0557 * cie_x - 0.2040 * luminance + 1.0570 * cie_z).max(0.),
+        );
+    }
+
+    fn sun(&self) -> Option<&DirectionalLight> {
+        return Some(&self.sun);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sky_is_blue_and_brightest_near_the_sun() {
+        let sky = PreethamSky::new(Vec3::new(0., 1., 1.), 3.);
+
+        let zenith = sky.radiance(Vec3::new(0., 1., 0.).normalize());
+        assert!(zenith.b > zenith.r);
+
+        let near_sun = sky.radiance(Vec3::new(0., 1., 1.1).normalize());
+        let away_from_sun = sky.radiance(Vec3::new(0., 1., -1.).normalize());
+        assert!(near_sun.g > away_from_sun.g);
+
+        // The setting sun is redder than the sun at noon
+        let noon = PreethamSky::new(Vec3::new(0., 1., 0.), 3.).sun.irradiance;
+        let sunset = PreethamSky::new(Vec3::new(0., 0.05, 1.), 3.).sun.irradiance;
+        assert!(sunset.r / sunset.b > noon.r / noon.b);
+    }
+}
@@ -1,16 +1,18 @@
 use std::f32::consts::PI;
+use std::sync::Arc;
 
-use crate::irt::{Aabb, Hit, Hittable, Interval, Material, Point, Ray, Vec3};
+use crate::irt::{is_masked, Aabb, Hit, Hittable, Interval, Material, Point, Ray, Texture, Vec3};
 
 #[derive(Debug)]
-pub struct Sphere<'a> {
+pub struct Sphere {
     pub center: Point,
     pub radius: f32,
-    pub material: &'a dyn Material,
+    pub material: Arc<dyn Material>,
     bounds: Aabb,
+    opacity: Option<Arc<dyn Texture>>,
 }
-impl<'a> Sphere<'a> {
-    pub fn new(center: Point, radius: f32, material: &'a dyn Material) -> Self {
+impl Sphere {
+    pub fn new(center: Point, radius: f32, material: Arc<dyn Material>) -> Self {
         let radius2 = radius.max(0.);
         let radius_vector = Vec3::new(radius2, radius2, radius2);
         let bounds = Aabb::new(center - radius_vector, center + radius_vector);
@@ -20,6 +22,15 @@ impl<'a> Sphere<'a> {
             radius: radius2,
             material,
             bounds,
+            opacity: None,
+        };
+    }
+
+    /// Cuts holes in the sphere where `opacity` is below 1. See [`is_masked`].
+    pub fn with_opacity(self, opacity: Arc<dyn Texture>) -> Self {
+        return Self {
+            opacity: Some(opacity),
+            ..self
         };
     }
 
@@ -31,9 +42,10 @@ impl<'a> Sphere<'a> {
         let v = theta / PI;
         return (u, v);
     }
-}
-impl Hittable for Sphere<'_> {
-    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit> {
+
+    /// Returns the `t` of the closest intersection within `t_interval`
+    /// which is not masked out by the opacity texture.
+    fn intersect(&self, ray: &Ray, t_interval: &Interval) -> Option<f32> {
         let oc = self.center - ray.origin;
         let a = ray.direction.length_squared();
         let h = ray.direction.dot(oc);
@@ -45,20 +57,52 @@ impl Hittable for Sphere<'_> {
         }
 
         let discriminant_sqrt = discriminant.sqrt();
-        let mut root = (h - discriminant_sqrt) / a;
-        if !t_interval.surrounds(root) {
-            root = (h + discriminant_sqrt) / a;
+        let roots = [(h - discriminant_sqrt) / a, (h + discriminant_sqrt) / a];
+        return roots.into_iter().find(|&root| {
             if !t_interval.surrounds(root) {
-                return None;
+                return false;
             }
-        }
+            let Some(opacity) = &self.opacity else {
+                return true;
+            };
 
-        let t = root;
+            let point = ray.at(root);
+            let (u, v) = Sphere::uv_at(((point - self.center) / self.radius).into());
+            return !is_masked(Some(opacity.as_ref()), u, v, point);
+        });
+    }
+}
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        let t = self.intersect(ray, t_interval)?;
         t_interval.max = t;
         let point = ray.at(t);
         let outward_normal = (point - self.center).normalize();
-        let (u, v) = Sphere::uv_at(outward_normal.as_vec3().into());
-        return Some(Hit::new(ray, point, outward_normal, t, self.material, u, v));
+        let normal = outward_normal.as_vec3();
+        let (u, v) = Sphere::uv_at(normal.into());
+        // The directions of increasing longitude and latitude, scaled to the length of
+        // a parallel and of half a meridian
+        let tangent = 2. * PI * self.radius * Vec3::new(normal.z, 0., -normal.x);
+        let sin_theta = (1. - normal.y * normal.y).sqrt();
+        let bitangent = match sin_theta > 0. {
+            true => {
+                let meridian = Vec3::new(
+                    -normal.y * normal.x,
+                    sin_theta * sin_theta,
+                    -normal.y * normal.z,
+                );
+                PI * self.radius / sin_theta * meridian
+            }
+            false => Vec3::new(0., 0., 0.),
+        };
+        return Some(
+            Hit::new(ray, point, outward_normal, t, self.material.as_ref(), u, v)
+                .with_tangents(tangent, bitangent),
+        );
+    }
+
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        return self.intersect(ray, t_interval).is_some();
     }
 
     fn bounds(&self) -> Aabb {
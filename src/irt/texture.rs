@@ -1,14 +1,24 @@
 use image::Rgb32FImage;
 
-use crate::irt::Point;
+use rand::random;
 
-use crate::irt::Color;
+use itertools::Itertools;
+
+use crate::irt::{Hit, Point};
+
+use crate::irt::{Color, ColorSpace, Error, Result};
 use std::fmt::Debug;
 
 use super::Perlin;
 
 pub trait Texture: Debug + Sync + Send {
     fn value(&self, u: f32, v: f32, point: Point) -> Color;
+
+    /// Returns the value at `hit`, averaged over the footprint of the ray cone that hit it
+    /// by textures which filter, such as [`ImageTexture`].
+    fn value_at(&self, hit: &Hit) -> Color {
+        return self.value(hit.u, hit.v, hit.point);
+    }
 }
 
 impl Texture for Color {
@@ -17,6 +27,19 @@ impl Texture for Color {
     }
 }
 
+/// Returns whether a hit at `(u, v, point)` is masked out by an `opacity` texture, which
+/// reads the red channel. Partial opacity lets rays through at random, in proportion.
+pub fn is_masked(opacity: Option<&dyn Texture>, u: f32, v: f32, point: Point) -> bool {
+    return opacity.is_some_and(|opacity| opacity.value(u, v, point).r <= random::<f32>());
+}
+
+/// A uniform grey, convenient for textures driving scalar parameters.
+impl Texture for f32 {
+    fn value(&self, _u: f32, _v: f32, _point: Point) -> Color {
+        return Color::new(*self, *self, *self);
+    }
+}
+
 #[derive(Debug)]
 pub struct CheckeredTexture {
     scale_inverted: f32,
@@ -47,30 +70,204 @@ impl Texture for CheckeredTexture {
     }
 }
 
+/// How an [`ImageTexture`] extends beyond the texture coordinates 0..1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Tiles the image.
+    Repeat,
+    /// Extends the edges of the image.
+    Clamp,
+    /// Tiles the image, flipping every other tile so that neighbouring tiles meet seamlessly.
+    Mirror,
+}
+impl WrapMode {
+    /// Returns the pixel at `index` along an axis `size` pixels long.
+    fn wrap(self, index: i64, size: u32) -> u32 {
+        let size = size as i64;
+        let index = match self {
+            WrapMode::Repeat => index.rem_euclid(size),
+            WrapMode::Clamp => index.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let index = index.rem_euclid(2 * size);
+                if index < size {
+                    index
+                } else {
+                    2 * size - 1 - index
+                }
+            }
+        };
+        return index as u32;
+    }
+}
+
+/// How an [`ImageTexture`] reconstructs the image between and across its pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFilter {
+    /// Takes the closest pixel.
+    Nearest,
+    /// Interpolates between the four closest pixels.
+    Bilinear,
+    /// Interpolates bilinearly in the two mip levels closest to the footprint
+    /// of the ray cone, and between them. Avoids the aliasing of distant textures.
+    Trilinear,
+}
+
 #[derive(Debug)]
 pub struct ImageTexture {
-    img: Rgb32FImage,
+    /// The image followed by its mip levels, each half the size of the previous one,
+    /// down to a single pixel.
+    mip_levels: Vec<Rgb32FImage>,
+    wrap: WrapMode,
+    filter: TextureFilter,
+    /// The number of times the image is repeated along `u` and `v`.
+    scale: (f32, f32),
+    /// The offset of the image in texture coordinates, after scaling.
+    offset: (f32, f32),
 }
 impl ImageTexture {
-    pub fn new(image_filename: &str) -> Self {
+    /// Loads an image whose pixels are encoded in `color_space`.
+    pub fn new(image_filename: &str, color_space: ColorSpace) -> Result<Self> {
+        let image = image::open(image_filename)
+            .map_err(|error| Error::image(image_filename, error))?
+            .to_rgb32f();
+        return Ok(Self::from_image(image, color_space));
+    }
+
+    fn from_image(mut image: Rgb32FImage, color_space: ColorSpace) -> Self {
+        // Mip levels must average linear values
+        color_space.decode_image(&mut image);
+
+        let mut mip_levels = vec![image];
+        loop {
+            let last = mip_levels.last().unwrap();
+            if last.width() <= 1 && last.height() <= 1 {
+                break;
+            }
+            let next = downsample(last);
+            mip_levels.push(next);
+        }
+
         return Self {
-            img: image::open(image_filename).unwrap().to_rgb32f(),
+            mip_levels,
+            wrap: WrapMode::Repeat,
+            filter: TextureFilter::Trilinear,
+            scale: (1., 1.),
+            offset: (0., 0.),
         };
     }
-}
-impl Texture for ImageTexture {
-    fn value(&self, u: f32, v: f32, _point: Point) -> Color {
-        if self.img.width() == 0 || self.img.height() == 0 {
+
+    pub fn with_wrap(self, wrap: WrapMode) -> Self {
+        return Self { wrap, ..self };
+    }
+
+    pub fn with_filter(self, filter: TextureFilter) -> Self {
+        return Self { filter, ..self };
+    }
+
+    /// Repeats the image `scale_u` times along `u` and `scale_v` times along `v`.
+    pub fn with_scale(self, scale_u: f32, scale_v: f32) -> Self {
+        return Self {
+            scale: (scale_u, scale_v),
+            ..self
+        };
+    }
+
+    /// Shifts the image by `offset_u` and `offset_v`, in scaled texture coordinates.
+    pub fn with_offset(self, offset_u: f32, offset_v: f32) -> Self {
+        return Self {
+            offset: (offset_u, offset_v),
+            ..self
+        };
+    }
+
+    fn pixel(&self, level: usize, x: i64, y: i64) -> Color {
+        let image = &self.mip_levels[level];
+        let x = self.wrap.wrap(x, image.width());
+        let y = self.wrap.wrap(y, image.height());
+        let pixel = image.get_pixel(x, y).0;
+        return Color::new(pixel[0], pixel[1], pixel[2]);
+    }
+
+    /// Returns the color at the scaled texture coordinates `(u, v)` of mip level `level`.
+    fn sample(&self, level: usize, u: f32, v: f32, bilinear: bool) -> Color {
+        let image = &self.mip_levels[level];
+        let x = u * image.width() as f32;
+        let y = (1. - v) * image.height() as f32;
+        if !bilinear {
+            return self.pixel(level, x.floor() as i64, y.floor() as i64);
+        }
+
+        // Pixel centers lie at half-integer coordinates
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = self.pixel(level, x0, y0) * (1. - tx) + self.pixel(level, x0 + 1, y0) * tx;
+        let bottom =
+            self.pixel(level, x0, y0 + 1) * (1. - tx) + self.pixel(level, x0 + 1, y0 + 1) * tx;
+        return top * (1. - ty) + bottom * ty;
+    }
+
+    /// Returns the color at `(u, v)`, averaged over `footprint` in texture coordinates.
+    fn filtered(&self, u: f32, v: f32, footprint: f32) -> Color {
+        let image = &self.mip_levels[0];
+        if image.width() == 0 || image.height() == 0 {
             return Color::cyan();
         }
 
-        let x = (u * self.img.width() as f32) as u32;
-        let y = ((1. - v) * self.img.height() as f32) as u32;
-        let pixel = self.img.get_pixel(x, y).0;
+        let u = u * self.scale.0 + self.offset.0;
+        let v = v * self.scale.1 + self.offset.1;
+        return match self.filter {
+            TextureFilter::Nearest => self.sample(0, u, v, false),
+            TextureFilter::Bilinear => self.sample(0, u, v, true),
+            TextureFilter::Trilinear => {
+                // The level at which the footprint covers a single pixel
+                let scale = self.scale.0.abs().max(self.scale.1.abs());
+                let pixels = footprint * scale * image.width().max(image.height()) as f32;
+                let last_level = (self.mip_levels.len() - 1) as f32;
+                let level = pixels.max(1.).log2().min(last_level);
 
-        return Color::new(pixel[0], pixel[1], pixel[2]);
+                let lower = level.floor();
+                let t = level - lower;
+                let lower = lower as usize;
+                let color = self.sample(lower, u, v, true);
+                if t == 0. {
+                    return color;
+                }
+                color * (1. - t) + self.sample(lower + 1, u, v, true) * t
+            }
+        };
     }
 }
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _point: Point) -> Color {
+        return self.filtered(u, v, 0.);
+    }
+
+    fn value_at(&self, hit: &Hit) -> Color {
+        return self.filtered(hit.u, hit.v, hit.texture_footprint());
+    }
+}
+
+/// Returns `image` at half its size, each pixel averaging up to four pixels.
+fn downsample(image: &Rgb32FImage) -> Rgb32FImage {
+    let width = image.width().div_ceil(2);
+    let height = image.height().div_ceil(2);
+    return Rgb32FImage::from_fn(width, height, |x, y| {
+        let xs = (2 * x)..(2 * x + 2).min(image.width());
+        let ys = (2 * y)..(2 * y + 2).min(image.height());
+        let mut sum = [0.; 3];
+        let mut count = 0.;
+        for (px, py) in xs.cartesian_product(ys) {
+            let pixel = image.get_pixel(px, py).0;
+            for channel in 0..3 {
+                sum[channel] += pixel[channel];
+            }
+            count += 1.;
+        }
+        return image::Rgb(sum.map(|channel| channel / count));
+    });
+}
 
 #[derive(Debug)]
 pub struct NoiseTexture {
@@ -90,3 +287,74 @@ impl Texture for NoiseTexture {
         return Color::white() * 0.5 * (1. + self.noise.noise(self.scale * point));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{Lambertian, Ray, Vec3};
+
+    /// Returns an image of `width` by 1 pixels, with the given grey levels.
+    fn strip(greys: &[f32]) -> ImageTexture {
+        let image = Rgb32FImage::from_fn(greys.len() as u32, 1, |x, _| {
+            let grey = greys[x as usize];
+            return image::Rgb([grey, grey, grey]);
+        });
+        return ImageTexture::from_image(image, ColorSpace::Linear);
+    }
+
+    #[test]
+    fn wrap_modes_map_indices_into_the_image() {
+        let indices = |mode: WrapMode| (-3..7).map(|i| mode.wrap(i, 3)).collect::<Vec<_>>();
+        assert_eq!(indices(WrapMode::Repeat), [0, 1, 2, 0, 1, 2, 0, 1, 2, 0]);
+        assert_eq!(indices(WrapMode::Clamp), [0, 0, 0, 0, 1, 2, 2, 2, 2, 2]);
+        assert_eq!(indices(WrapMode::Mirror), [2, 1, 0, 0, 1, 2, 2, 1, 0, 0]);
+    }
+
+    #[test]
+    fn edges_of_texture_coordinates_stay_in_the_image() {
+        let point = Point::new(0., 0., 0.);
+        let texture = strip(&[0., 1.]).with_filter(TextureFilter::Nearest);
+        assert_eq!(texture.value(1., 0., point).r, 0.);
+
+        let texture = texture.with_wrap(WrapMode::Clamp);
+        assert_eq!(texture.value(1., 1., point).r, 1.);
+    }
+
+    #[test]
+    fn bilinear_filter_interpolates_pixel_centers() {
+        let point = Point::new(0., 0., 0.);
+        let texture = strip(&[0., 1.])
+            .with_filter(TextureFilter::Bilinear)
+            .with_wrap(WrapMode::Clamp);
+        assert_eq!(texture.value(0.25, 0.5, point).r, 0.);
+        assert_eq!(texture.value(0.5, 0.5, point).r, 0.5);
+        assert_eq!(texture.value(0.75, 0.5, point).r, 1.);
+    }
+
+    #[test]
+    fn wide_footprints_read_averaged_mip_levels() {
+        let texture = strip(&[0., 1., 0., 1.]);
+        assert_eq!(texture.mip_levels.len(), 3);
+        assert_eq!(texture.mip_levels[2].get_pixel(0, 0).0, [0.5; 3]);
+
+        let material = Lambertian::new(Box::new(Color::white()));
+        let normal = Vec3::new(0., 1., 0.).normalize();
+        let hit_with_cone = |cone_width: f32| {
+            let ray = Ray::new(Point::new(0., 1., 0.), -normal.as_vec3()).with_cone(cone_width, 0.);
+            return Hit::new(
+                &ray,
+                Point::new(0., 0., 0.),
+                normal,
+                1.,
+                &material,
+                0.375,
+                0.5,
+            )
+            .with_tangents(Vec3::new(1., 0., 0.), Vec3::new(0., 0., -1.));
+        };
+
+        // A footprint below a pixel sees the image itself, one covering it sees its average
+        assert_eq!(texture.value_at(&hit_with_cone(0.)).r, 1.);
+        assert_eq!(texture.value_at(&hit_with_cone(1.)).r, 0.5);
+    }
+}
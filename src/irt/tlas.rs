@@ -0,0 +1,139 @@
+use crate::irt::{Aabb, Bvh, Hit, Hittable, Instance, Interval, Ray};
+
+/// An instance in a [`Tlas`]: any bottom-level acceleration structure (BLAS),
+/// e.g. a [`Bvh`] of spheres, quads, or triangles, placed in the world by a transform.
+pub type TlasInstance = Instance<dyn Hittable>;
+
+/// An instance with its index in the list the TLAS was created from.
+struct TlasEntry {
+    instance: TlasInstance,
+    id: usize,
+}
+impl Hittable for TlasEntry {
+    fn bounds(&self) -> Aabb {
+        return self.instance.bounds();
+    }
+
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        return self.instance.hit(ray, t_interval);
+    }
+
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        return self.instance.occluded(ray, t_interval);
+    }
+}
+
+/// A top-level acceleration structure (TLAS) over instances of different BLAS types.
+///
+/// When only the transforms of instances change, the TLAS is refitted instead of rebuilt,
+/// which is cheap as there are usually few instances.
+pub struct Tlas {
+    bvh: Bvh<TlasEntry>,
+    /// The position of each instance in the BVH, indexed by the order
+    /// the instances were given in.
+    positions: Vec<usize>,
+}
+impl Tlas {
+    /// How much the SAH cost may degrade through refitting before the TLAS is rebuilt.
+    const MAX_COST_RATIO: f32 = 1.5;
+
+    pub fn new(instances: Vec<TlasInstance>) -> Self {
+        let entries: Vec<TlasEntry> = instances
+            .into_iter()
+            .enumerate()
+            .map(|(id, instance)| TlasEntry { instance, id })
+            .collect();
+
+        let mut tlas = Self {
+            positions: vec![0; entries.len()],
+            bvh: Bvh::new(entries),
+        };
+        tlas.update_positions();
+
+        return tlas;
+    }
+
+    fn update_positions(&mut self) {
+        for (position, entry) in self.bvh.hittables.iter().enumerate() {
+            self.positions[entry.id] = position;
+        }
+    }
+
+    /// Returns the instance at `index` in the list the TLAS was created from.
+    pub fn instance_mut(&mut self, index: usize) -> &mut TlasInstance {
+        return &mut self.bvh.hittables_mut()[self.positions[index]].instance;
+    }
+
+    /// Updates the TLAS after instances have moved, rebuilding it only
+    /// if refitting degrades it too much.
+    pub fn refit(&mut self) {
+        if self.bvh.refit_or_rebuild(Self::MAX_COST_RATIO) {
+            self.update_positions();
+        }
+    }
+}
+impl Hittable for Tlas {
+    fn bounds(&self) -> Aabb {
+        return self.bvh.bounds();
+    }
+
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        return self.bvh.hit(ray, t_interval);
+    }
+
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        return self.bvh.occluded(ray, t_interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{Color, Lambertian, Material, Matrix, Point, Quad, Sphere, Vec3, Visibility};
+    use std::sync::Arc;
+
+    #[test]
+    fn refit_tracks_moved_instances() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let spheres = Arc::new(Bvh::new(vec![Sphere::new(
+            Point::new(0., 0., 0.),
+            1.,
+            material.clone(),
+        )]));
+        let quads = Arc::new(Bvh::new(Quad::cube(material)));
+        let mut tlas = Tlas::new(vec![
+            TlasInstance::new(spheres, Matrix::identity()),
+            TlasInstance::new(quads, Matrix::identity().translate(5., 0., 0.)),
+        ]);
+
+        let ray = Ray::new(Point::new(0., 0., -5.), Vec3::new(0., 0., 1.));
+        let hit = tlas.hit(&ray, &mut Interval::new(0.001, f32::INFINITY));
+        assert_eq!(hit.map(|hit| hit.t), Some(4.));
+
+        tlas.instance_mut(0)
+            .set_transform(Matrix::identity().translate(0., 0., 2.));
+        tlas.refit();
+        let hit = tlas.hit(&ray, &mut Interval::new(0.001, f32::INFINITY));
+        assert_eq!(hit.map(|hit| hit.t), Some(6.));
+    }
+
+    #[test]
+    fn mask_hides_instances() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let spheres = Arc::new(Bvh::new(vec![Sphere::new(
+            Point::new(0., 0., 0.),
+            1.,
+            material,
+        )]));
+        let tlas = Tlas::new(vec![TlasInstance::new(spheres, Matrix::identity())
+            .with_visibility(Visibility {
+                camera: false,
+                ..Visibility::ALL
+            })]);
+
+        let ray = Ray::new(Point::new(0., 0., -5.), Vec3::new(0., 0., 1.));
+        let interval = Interval::new(0.001, f32::INFINITY);
+        assert!(!tlas.occluded(&ray.with_mask(Ray::CAMERA), &interval));
+        assert!(tlas.occluded(&ray.with_mask(Ray::REFLECTION), &interval));
+    }
+}
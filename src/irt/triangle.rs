@@ -1,16 +1,21 @@
-use crate::irt::{Aabb, Hit, Hittable, Interval, Material, Point, Ray};
+use std::sync::Arc;
+
+use crate::irt::{is_masked, Aabb, Hit, Hittable, Interval, Material, Point, Ray, Texture, Vec3};
 
 #[derive(Debug)]
-pub struct Triangle<'a> {
+pub struct Triangle {
     pub a: Point,
     pub b: Point,
     pub c: Point,
     pub centroid: Point,
     bounds: Aabb,
-    pub material: &'a dyn Material,
+    pub material: Arc<dyn Material>,
+    /// The texture coordinates of `a`, `b` and `c`.
+    uvs: Option<[(f32, f32); 3]>,
+    opacity: Option<Arc<dyn Texture>>,
 }
-impl<'a> Triangle<'a> {
-    pub fn new(a: Point, b: Point, c: Point, material: &'a dyn Material) -> Self {
+impl Triangle {
+    pub fn new(a: Point, b: Point, c: Point, material: Arc<dyn Material>) -> Self {
         let centroid = (a + b + c) * (1. / 3.);
         let bounds = Aabb::new(a.min(b.min(c)), a.max(b.max(c)));
 
@@ -21,19 +26,78 @@ impl<'a> Triangle<'a> {
             centroid,
             bounds,
             material,
+            uvs: None,
+            opacity: None,
         };
     }
-}
-impl Hittable for Triangle<'_> {
-    fn bounds(&self) -> Aabb {
-        return self.bounds;
+
+    /// Cuts holes in the triangle where `opacity` is below 1. See [`is_masked`].
+    pub fn with_opacity(self, opacity: Arc<dyn Texture>) -> Self {
+        return Self {
+            opacity: Some(opacity),
+            ..self
+        };
     }
 
-    fn centroid(&self) -> Point {
-        return self.centroid;
+    /// Maps a texture onto the triangle, with the texture coordinates of `a`, `b` and `c`.
+    pub fn with_uvs(self, uvs: [(f32, f32); 3]) -> Self {
+        return Self {
+            uvs: Some(uvs),
+            ..self
+        };
+    }
+
+    /// Returns the texture coordinates of `a`, `b` and `c`, if the triangle has any.
+    pub fn uvs(&self) -> Option<[(f32, f32); 3]> {
+        return self.uvs;
+    }
+
+    /// Returns whether the triangle has no holes cut by an opacity texture.
+    pub fn is_opaque(&self) -> bool {
+        return self.opacity.is_none();
+    }
+
+    /// Returns the texture coordinates at the barycentric coordinates `(b, c)`, and the
+    /// tangent along which `u` increases.
+    /// Without texture coordinates, the tangent follows the edge from `a` to `b`.
+    fn surface_at(&self, b: f32, c: f32) -> ((f32, f32), (Vec3, Vec3)) {
+        let edges = (self.b - self.a, self.c - self.a);
+        let Some([uv_a, uv_b, uv_c]) = self.uvs else {
+            return ((0., 0.), edges);
+        };
+
+        let a = 1. - b - c;
+        let uv = (
+            a * uv_a.0 + b * uv_b.0 + c * uv_c.0,
+            a * uv_a.1 + b * uv_b.1 + c * uv_c.1,
+        );
+
+        // Solve for the derivatives of the position with respect to u and v
+        let (du_ac, dv_ac) = (uv_a.0 - uv_c.0, uv_a.1 - uv_c.1);
+        let (du_bc, dv_bc) = (uv_b.0 - uv_c.0, uv_b.1 - uv_c.1);
+        let determinant = du_ac * dv_bc - dv_ac * du_bc;
+        if determinant.abs() < 1e-12 {
+            return (uv, edges);
+        }
+        let tangent = (dv_bc * (self.a - self.c) - dv_ac * (self.b - self.c)) / determinant;
+        let bitangent = (du_ac * (self.b - self.c) - du_bc * (self.a - self.c)) / determinant;
+        return (uv, (tangent, bitangent));
+    }
+
+    /// Moves the triangle to the vertices `a`, `b`, and `c`,
+    /// updating its centroid and bounds.
+    pub fn set_vertices(&mut self, a: Point, b: Point, c: Point) {
+        self.a = a;
+        self.b = b;
+        self.c = c;
+        self.centroid = (a + b + c) * (1. / 3.);
+        self.bounds = Aabb::new(a.min(b.min(c)), a.max(b.max(c)));
     }
 
-    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit> {
+    /// Returns the `t` of the intersection within `t_interval` and its barycentric
+    /// coordinates, the weights of `b` and `c`, using the Möller–Trumbore algorithm.
+    /// Intersections masked out by the opacity texture are ignored.
+    fn intersect(&self, ray: &Ray, t_interval: &Interval) -> Option<(f32, f32, f32)> {
         let edge_1 = self.b - self.a;
         let edge_2 = self.c - self.a;
 
@@ -63,15 +127,70 @@ impl Hittable for Triangle<'_> {
             return None;
         }
 
+        if let Some(opacity) = &self.opacity {
+            let ((texture_u, texture_v), _) = self.surface_at(u, v);
+            if is_masked(Some(opacity.as_ref()), texture_u, texture_v, ray.at(t)) {
+                return None;
+            }
+        }
+
+        return Some((t, u, v));
+    }
+}
+impl Hittable for Triangle {
+    fn bounds(&self) -> Aabb {
+        return self.bounds;
+    }
+
+    fn centroid(&self) -> Point {
+        return self.centroid;
+    }
+
+    fn hit(&self, ray: &Ray, t_interval: &mut Interval) -> Option<Hit<'_>> {
+        let (t, b, c) = self.intersect(ray, t_interval)?;
+        let ((u, v), (tangent, bitangent)) = self.surface_at(b, c);
+
         t_interval.max = t;
-        return Some(Hit::new(
-            ray,
-            ray.origin + ray.direction * t,
-            edge_2.cross(edge_1).normalize(),
-            t,
-            self.material,
-            0.,
-            0.,
-        ));
+        return Some(
+            Hit::new(
+                ray,
+                ray.origin + ray.direction * t,
+                (self.c - self.a).cross(self.b - self.a).normalize(),
+                t,
+                self.material.as_ref(),
+                u,
+                v,
+            )
+            .with_tangents(tangent, bitangent),
+        );
+    }
+
+    fn occluded(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        return self.intersect(ray, t_interval).is_some();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irt::{approx_equals, Lambertian, Vec3};
+
+    #[test]
+    fn uv_mapped_triangle_has_uv_tangent() {
+        let material = Arc::new(Lambertian::new(Box::new(0.5)));
+        let triangle = Triangle::new(
+            Point::new(0., 0., 0.),
+            Point::new(2., 0., 0.),
+            Point::new(0., 0., 2.),
+            material,
+        )
+        .with_uvs([(0., 0.), (0., 1.), (1., 0.)]);
+
+        let ray = Ray::new(Point::new(0.5, 1., 1.), Vec3::new(0., -1., 0.));
+        let hit = triangle.hit(&ray, &mut Interval::new(0., 10.)).unwrap();
+        assert!(approx_equals(hit.u, 0.5));
+        assert!(approx_equals(hit.v, 0.25));
+        assert_eq!(hit.tangent, Vec3::new(0., 0., 2.));
+        assert_eq!(hit.bitangent, Vec3::new(2., 0., 0.));
     }
 }
@@ -11,10 +11,6 @@ pub fn approx_equals(a: f32, b: f32) -> bool {
 #[derive(Debug, Copy, Clone)]
 pub struct Degrees(pub f32);
 impl Degrees {
-    pub fn as_f32(self) -> f32 {
-        return self.0;
-    }
-
     pub fn to_radians(self) -> Radians {
         return Radians(self.0.to_radians());
     }
@@ -25,8 +21,4 @@ impl Radians {
     pub fn as_f32(self) -> f32 {
         return self.0;
     }
-
-    pub fn to_degrees(self) -> Degrees {
-        return Degrees(self.0.to_degrees());
-    }
 }
@@ -125,7 +125,7 @@ impl Add for Vec3 {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
-        return Self::new(self.x + rhs.x, self.y + rhs.y, self.z - rhs.z);
+        return Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z);
     }
 }
 impl Sub for Vec3 {
@@ -2,25 +2,26 @@ mod irt;
 use irt::*;
 
 use std::{
-    fs::File,
+    fs::{self, File},
     io::{BufRead, BufReader},
-    rc::Rc,
+    process,
+    sync::Arc,
     time::Instant,
 };
 
-fn basic_scene() {
-    let material_ground = Lambertian::new(Box::new(Color::new(0.8, 0.8, 0.)));
-    let material_center = Lambertian::new(Box::new(Color::new(0.1, 0.2, 0.5)));
-    let material_left = Dielectric::new(1.5);
-    let material_bubble = Dielectric::new(1. / 1.5);
-    let material_right = Metal::new(Color::new(0.8, 0.6, 0.2), 0.7);
+fn basic_scene() -> Result<()> {
+    let material_ground = Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.8, 0.))));
+    let material_center = Arc::new(Lambertian::new(Box::new(Color::new(0.1, 0.2, 0.5))));
+    let material_left = Arc::new(Dielectric::new(1.5));
+    let material_bubble = Arc::new(Dielectric::new(1. / 1.5));
+    let material_right = Arc::new(Conductor::gold(0.4));
 
     let world = vec![
-        Sphere::new(Point::new(0., -100.5, -1.), 100., &material_ground),
-        Sphere::new(Point::new(0., 0., -1.2), 0.5, &material_center),
-        Sphere::new(Point::new(-1., 0., -1.), 0.5, &material_left),
-        Sphere::new(Point::new(-1., 0., -1.), 0.4, &material_bubble),
-        Sphere::new(Point::new(1., 0., -1.), 0.5, &material_right),
+        Sphere::new(Point::new(0., -100.5, -1.), 100., material_ground.clone()),
+        Sphere::new(Point::new(0., 0., -1.2), 0.5, material_center.clone()),
+        Sphere::new(Point::new(-1., 0., -1.), 0.5, material_left.clone()),
+        Sphere::new(Point::new(-1., 0., -1.), 0.4, material_bubble.clone()),
+        Sphere::new(Point::new(1., 0., -1.), 0.5, material_right.clone()),
     ];
 
     let look_from = Point::new(-2., 2., 1.);
@@ -40,19 +41,21 @@ fn basic_scene() {
 
     let bvh = Bvh::new(world);
     camera.render(&bvh);
+
+    return Ok(());
 }
 
-fn checkered_spheres() {
+fn checkered_spheres() -> Result<()> {
     let white_green_checker = CheckeredTexture::new(
         0.4,
         Box::new(Color::new(0.2, 0.3, 0.1)),
         Box::new(Color::new(0.9, 0.9, 0.9)),
     );
-    let material = Lambertian::new(Box::new(white_green_checker));
+    let material = Arc::new(Lambertian::new(Box::new(white_green_checker)));
 
     let world = vec![
-        Sphere::new(Point::new(0., -10., 0.), 10., &material),
-        Sphere::new(Point::new(0., 10., 0.), 10., &material),
+        Sphere::new(Point::new(0., -10., 0.), 10., material.clone()),
+        Sphere::new(Point::new(0., 10., 0.), 10., material.clone()),
     ];
 
     let look_from = Point::new(13., 2., 3.);
@@ -71,13 +74,19 @@ fn checkered_spheres() {
 
     let bvh = Bvh::new(world);
     camera.render(&bvh);
+
+    return Ok(());
 }
 
-fn earth() {
-    let earth_texture = ImageTexture::new("assets/earthmap.jpg");
-    let earth_material = Lambertian::new(Box::new(earth_texture));
+fn earth() -> Result<()> {
+    let earth_texture = ImageTexture::new("assets/earthmap.jpg", ColorSpace::Srgb)?;
+    let earth_material = Arc::new(Lambertian::new(Box::new(earth_texture)));
 
-    let world = vec![Sphere::new(Point::new(0., 0., 0.), 2., &earth_material)];
+    let world = vec![Sphere::new(
+        Point::new(0., 0., 0.),
+        2.,
+        earth_material.clone(),
+    )];
 
     let look_from = Point::new(0., 12., 5.);
     let look_at = Point::new(0., 0., 0.);
@@ -95,15 +104,17 @@ fn earth() {
 
     let bvh = Bvh::new(world);
     camera.render(&bvh);
+
+    return Ok(());
 }
 
-fn noise_scene() {
+fn noise_scene() -> Result<()> {
     let texture = NoiseTexture::new(4.);
-    let material = Lambertian::new(Box::new(texture));
+    let material = Arc::new(Lambertian::new(Box::new(texture)));
 
     let world = vec![
-        Sphere::new(Point::new(0., -1000., 0.), 1000., &material),
-        Sphere::new(Point::new(0., 2., 0.), 2., &material),
+        Sphere::new(Point::new(0., -1000., 0.), 1000., material.clone()),
+        Sphere::new(Point::new(0., 2., 0.), 2., material.clone()),
     ];
 
     let look_from = Point::new(13., 2., 3.);
@@ -122,48 +133,50 @@ fn noise_scene() {
 
     let bvh = Bvh::new(world);
     camera.render(&bvh);
+
+    return Ok(());
 }
 
-fn quads() {
-    let left_red = Lambertian::new(Box::new(Color::new(1.0, 0.2, 0.2)));
-    let back_green = Lambertian::new(Box::new(Color::new(0.2, 1.0, 0.2)));
-    let upper_orange = Lambertian::new(Box::new(Color::new(1.0, 0.5, 0.)));
+fn quads() -> Result<()> {
+    let left_red = Arc::new(Lambertian::new(Box::new(Color::new(1.0, 0.2, 0.2))));
+    let back_green = Arc::new(Lambertian::new(Box::new(Color::new(0.2, 1.0, 0.2))));
+    let upper_orange = Arc::new(Lambertian::new(Box::new(Color::new(1.0, 0.5, 0.))));
 
-    let earth_texture = ImageTexture::new("assets/earthmap.jpg");
-    let earth_material = Lambertian::new(Box::new(earth_texture));
+    let earth_texture = ImageTexture::new("assets/earthmap.jpg", ColorSpace::Srgb)?;
+    let earth_material = Arc::new(Lambertian::new(Box::new(earth_texture)));
 
-    let noise_material = Lambertian::new(Box::new(NoiseTexture::new(4.)));
+    let noise_material = Arc::new(Lambertian::new(Box::new(NoiseTexture::new(4.))));
 
     let world = vec![
         Quad::new(
             Point::new(-3., -2., 5.),
             Vec3::new(0., 0., -4.),
             Vec3::new(0., 4., 0.),
-            &left_red,
+            left_red.clone(),
         ),
         Quad::new(
             Point::new(-2., -2., 0.),
             Vec3::new(4., 0., 0.),
             Vec3::new(0., 4., 0.),
-            &back_green,
+            back_green.clone(),
         ),
         Quad::new(
             Point::new(3., -2., 1.),
             Vec3::new(0., 0., 4.),
             Vec3::new(0., 4., 0.),
-            &earth_material,
+            earth_material.clone(),
         ),
         Quad::new(
             Point::new(-2., 3., 1.),
             Vec3::new(4., 0., 0.),
             Vec3::new(0., 0., 4.),
-            &upper_orange,
+            upper_orange.clone(),
         ),
         Quad::new(
             Point::new(-2., -3., 5.),
             Vec3::new(4., 0., 0.),
             Vec3::new(0., 0., -4.),
-            &noise_material,
+            noise_material.clone(),
         ),
     ];
 
@@ -183,24 +196,96 @@ fn quads() {
 
     let bvh = Bvh::new(world);
     camera.render(&bvh);
+
+    return Ok(());
 }
 
-fn simple_light() {
+/// The earth map on four quads: centered on each quad with a margin, which the image
+/// fills by repeating, clamping or mirroring, and a magnified detail without filtering.
+fn texture_wrapping() -> Result<()> {
+    let earth = |wrap: WrapMode| -> Result<Arc<Lambertian>> {
+        let texture = ImageTexture::new("assets/earthmap.jpg", ColorSpace::Srgb)?
+            .with_scale(2., 2.)
+            .with_offset(-0.5, -0.5)
+            .with_wrap(wrap);
+        return Ok(Arc::new(Lambertian::new(Box::new(texture))));
+    };
+    let detail = ImageTexture::new("assets/earthmap.jpg", ColorSpace::Srgb)?
+        .with_scale(0.05, 0.05)
+        .with_offset(0.3, 0.6)
+        .with_filter(TextureFilter::Nearest);
+
+    let quad_at = |x: f32, y: f32, material: Arc<Lambertian>| {
+        return Quad::new(
+            Point::new(x, y, 0.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 2., 0.),
+            material,
+        );
+    };
+    let world = vec![
+        quad_at(-2.1, 0.1, earth(WrapMode::Repeat)?),
+        quad_at(0.1, 0.1, earth(WrapMode::Clamp)?),
+        quad_at(-2.1, -2.1, earth(WrapMode::Mirror)?),
+        quad_at(0.1, -2.1, Arc::new(Lambertian::new(Box::new(detail)))),
+    ];
+
+    let look_from = Point::new(0., 0., 6.);
+    let look_at = Point::new(0., 0., 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        1.,
+        Degrees(45.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::new(0.7, 0.8, 1.),
+    );
+
+    camera.render(&Bvh::new(world));
+
+    return Ok(());
+}
+
+fn simple_light() -> Result<()> {
     let noise_texture = NoiseTexture::new(4.);
-    let noise_material = Lambertian::new(Box::new(noise_texture));
+    let noise_material = Arc::new(Lambertian::new(Box::new(noise_texture)));
 
-    let light_material = DiffuseLight::new(Box::new(Color::new(4., 4., 4.)));
+    let light_material = Arc::new(DiffuseLight::new(Box::new(Color::new(4., 4., 4.))));
 
-    let ground = Sphere::new(Point::new(0., -1000., 0.), 1000., &noise_material);
-    let ball = Sphere::new(Point::new(0., 2., 0.), 2., &noise_material);
-    let quad_light = Quad::new(
+    // A single sphere mesh is instanced for the ground, the ball, and the spherical light
+    let unit_sphere: Arc<dyn Hittable> = Arc::new(Bvh::new(vec![Sphere::new(
+        Point::new(0., 0., 0.),
+        1.,
+        noise_material,
+    )]));
+    let quad_light: Arc<dyn Hittable> = Arc::new(Bvh::new(vec![Quad::new(
         Point::new(3., 1., -2.),
         Vec3::new(2., 0., 0.),
         Vec3::new(0., 2., 0.),
-        &light_material,
-    );
-    let sphere_light = Sphere::new(Point::new(0., 7., 0.), 2., &light_material);
-    let world: Vec<&dyn Hittable> = vec![&ground, &ball, &quad_light, &sphere_light];
+        light_material.clone(),
+    )]));
+
+    let world = Tlas::new(vec![
+        TlasInstance::new(
+            unit_sphere.clone(),
+            Matrix::identity()
+                .scale(1000., 1000., 1000.)
+                .translate(0., -1000., 0.),
+        ),
+        TlasInstance::new(
+            unit_sphere.clone(),
+            Matrix::identity().scale(2., 2., 2.).translate(0., 2., 0.),
+        ),
+        TlasInstance::new(
+            unit_sphere,
+            Matrix::identity().scale(2., 2., 2.).translate(0., 7., 0.),
+        )
+        .with_material(MaterialOverride::Replace(light_material)),
+        TlasInstance::new(quad_light, Matrix::identity()),
+    ]);
 
     let look_from = Point::new(26., 3., 6.);
     let look_at = Point::new(0., 2., 0.);
@@ -216,76 +301,215 @@ fn simple_light() {
         Color::black(),
     );
 
-    let bvh = Bvh::new(world);
-    camera.render(&bvh);
+    camera.render(&world);
+
+    return Ok(());
 }
 
-fn cornell_box() {
-    let red = Lambertian::new(Box::new(Color::new(0.65, 0.05, 0.05)));
-    let green = Lambertian::new(Box::new(Color::new(0.12, 0.45, 0.15)));
-    let white = Lambertian::new(Box::new(Color::new(0.73, 0.73, 0.73)));
-    let light = DiffuseLight::new(Box::new(Color::new(15., 15., 15.)));
+/// Spheres in front of a mirror, with visibility masks: the middle sphere is hidden from
+/// the camera but seen in the mirror, and the right sphere casts no shadow.
+fn visibility_masks() -> Result<()> {
+    let floor = Arc::new(Lambertian::new(Box::new(Color::new(0.6, 0.6, 0.6))));
+    let mirror = Arc::new(Conductor::aluminium(0.02));
+    let red = Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.1, 0.1))));
+
+    let unit_sphere: Arc<dyn Hittable> = Arc::new(Bvh::new(vec![Sphere::new(
+        Point::new(0., 0., 0.),
+        1.,
+        floor,
+    )]));
+    let mirror_quad: Arc<dyn Hittable> = Arc::new(Bvh::new(vec![Quad::new(
+        Point::new(-4., 0., 0.),
+        Vec3::new(8., 0., 0.),
+        Vec3::new(0., 4., 0.),
+        mirror,
+    )]));
+
+    let sphere_at = |x: f32| Matrix::identity().translate(x, 1., 0.);
+    let world = Tlas::new(vec![
+        TlasInstance::new(
+            unit_sphere.clone(),
+            Matrix::identity()
+                .scale(1000., 1000., 1000.)
+                .translate(0., -1000., 0.),
+        ),
+        // Leaning back, so that the mirror shows the tops of the spheres
+        TlasInstance::new(
+            mirror_quad,
+            Matrix::identity()
+                .rotate_x(Degrees(-10.))
+                .translate(0., 0., -2.5),
+        ),
+        TlasInstance::new(unit_sphere.clone(), sphere_at(-2.2))
+            .with_material(MaterialOverride::Replace(red.clone())),
+        TlasInstance::new(unit_sphere.clone(), sphere_at(0.))
+            .with_material(MaterialOverride::Replace(red.clone()))
+            .with_visibility(Visibility {
+                camera: false,
+                ..Visibility::ALL
+            }),
+        TlasInstance::new(unit_sphere, sphere_at(2.2))
+            .with_material(MaterialOverride::Replace(red))
+            .with_visibility(Visibility {
+                shadow: false,
+                ..Visibility::ALL
+            }),
+    ]);
 
-    let outer_box = Bvh::new(vec![
+    let look_from = Point::new(0., 3., 9.);
+    let look_at = Point::new(0., 1., 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(35.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::new(0.1, 0.1, 0.15),
+    )
+    .with_light(DirectionalLight::new(
+        Vec3::new(-1., 2., 1.),
+        Color::new(2., 2., 2.),
+    ));
+
+    camera.render(&world);
+
+    return Ok(());
+}
+
+/// Renders the frames of a textured ball rolling across the floor, to `frame_<n>.ppm`.
+/// Only the transform of the ball changes between frames, so the TLAS is refitted.
+fn rolling_ball() -> Result<()> {
+    const FRAMES: usize = 8;
+
+    let floor = Arc::new(Lambertian::new(Box::new(Color::new(0.5, 0.5, 0.5))));
+    // Texture coordinates move with the ball, unlike solid textures which would stay in place
+    let earth_texture = ImageTexture::new("assets/earthmap.jpg", ColorSpace::Srgb)?;
+    let ball_material = Arc::new(Lambertian::new(Box::new(earth_texture)));
+
+    let ball: Arc<dyn Hittable> = Arc::new(Bvh::new(vec![Sphere::new(
+        Point::new(0., 0., 0.),
+        1.,
+        ball_material,
+    )]));
+    let floor: Arc<dyn Hittable> = Arc::new(Bvh::new(vec![Sphere::new(
+        Point::new(0., -1000., 0.),
+        1000.,
+        floor,
+    )]));
+    let mut world = Tlas::new(vec![
+        TlasInstance::new(ball, Matrix::identity()),
+        TlasInstance::new(floor, Matrix::identity()),
+    ]);
+
+    let look_from = Point::new(0., 3., 12.);
+    let look_at = Point::new(0., 1., 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(35.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::new(0.7, 0.8, 1.),
+    );
+
+    for frame in 0..FRAMES {
+        // The unit ball rolls without slipping, turning by one radian per unit traveled
+        let x = -4. + 8. * frame as f32 / (FRAMES - 1) as f32;
+        world.instance_mut(0).set_transform(
+            Matrix::identity()
+                .rotate_z(Degrees(-x.to_degrees()))
+                .translate(x, 1., 0.),
+        );
+        world.refit();
+
+        camera.render(&world);
+        let frame_path = format!("frame_{frame}.ppm");
+        fs::rename("image.ppm", &frame_path).map_err(|error| Error::io(&frame_path, error))?;
+    }
+
+    return Ok(());
+}
+
+/// Returns the walls and the light of the Cornell box.
+fn cornell_box_walls() -> BVHInstance<Quad> {
+    let red = Arc::new(Lambertian::new(Box::new(Color::new(0.65, 0.05, 0.05))));
+    let green = Arc::new(Lambertian::new(Box::new(Color::new(0.12, 0.45, 0.15))));
+    let white = Arc::new(Lambertian::new(Box::new(Color::new(0.73, 0.73, 0.73))));
+    let light = Arc::new(DiffuseLight::new(Box::new(Color::new(15., 15., 15.))));
+
+    let outer_box = Arc::new(Bvh::new(vec![
         Quad::new(
             Point::new(555., 0., 0.),
             Vec3::new(0., 555., 0.),
             Vec3::new(0., 0., 555.),
-            &green,
+            green.clone(),
         ),
         Quad::new(
             Point::new(0., 0., 0.),
             Vec3::new(0., 555., 0.),
             Vec3::new(0., 0., 555.),
-            &red,
+            red.clone(),
         ),
         Quad::new(
             Point::new(343., 554., 332.),
             Vec3::new(-130., 0., 0.),
             Vec3::new(0., 0., -105.),
-            &light,
+            light.clone(),
         ),
         Quad::new(
             Point::new(0., 0., 0.),
             Vec3::new(555., 0., 0.),
             Vec3::new(0., 0., 555.),
-            &white,
+            white.clone(),
         ),
         Quad::new(
             Point::new(0., 555., 0.),
             Vec3::new(555., 0., 0.),
             Vec3::new(0., 0., 555.),
-            &white,
+            white.clone(),
         ),
         Quad::new(
             Point::new(0., 0., 555.),
             Vec3::new(555., 0., 0.),
             Vec3::new(0., 555., 0.),
-            &white,
+            white.clone(),
         ),
-    ]);
-    let outer_box_instance = BVHInstance::new(&outer_box, Matrix::identity());
+    ]));
+    return BVHInstance::new(outer_box, Matrix::identity());
+}
 
-    let cube = Bvh::new(Quad::cube(&white));
-    let box1_instance = BVHInstance::new(
-        &cube,
+/// Returns the tall and the short box of the Cornell box.
+fn cornell_box_boxes(material: Arc<dyn Material>) -> (BVHInstance<Quad>, BVHInstance<Quad>) {
+    let cube = Arc::new(Bvh::new(Quad::cube(material)));
+    let tall_box = BVHInstance::new(
+        cube.clone(),
         Matrix::identity()
             .scale(165., 330., 165.)
             .translate(150., 0., 370.)
             .rotate_y(Degrees(15.)),
     );
-    let box2_instance = BVHInstance::new(
-        &cube,
+    let short_box = BVHInstance::new(
+        cube,
         Matrix::identity()
             .scale(165., 165., 165.)
             .translate(160., 0., 65.)
             .rotate_y(Degrees(-18.)),
     );
 
+    return (tall_box, short_box);
+}
+
+fn cornell_box_camera() -> Camera {
     let look_from = Point::new(278., 278., -800.);
     let look_at = Point::new(278., 278., 0.);
     let up = Vec3::new(0., 1., 0.);
-    let camera = Camera::new(
+    return Camera::new(
         1.,
         Degrees(40.),
         600,
@@ -295,46 +519,283 @@ fn cornell_box() {
         200,
         Color::black(),
     );
+}
 
-    camera.render(&Bvh::new(vec![
-        outer_box_instance,
-        box1_instance,
-        box2_instance,
-    ]));
+/// Returns the Cornell box. The scene owns its materials and meshes,
+/// so it can be built separately from the camera that renders it.
+fn cornell_box_world() -> Bvh<BVHInstance<Quad>> {
+    let white = Arc::new(Lambertian::new(Box::new(Color::new(0.73, 0.73, 0.73))));
+    let (tall_box, short_box) = cornell_box_boxes(white);
+
+    return Bvh::new(vec![cornell_box_walls(), tall_box, short_box]);
+}
+
+fn cornell_box() -> Result<()> {
+    let world = cornell_box_world();
+    cornell_box_camera().render(&world);
+
+    return Ok(());
+}
+
+/// The Cornell box with its boxes replaced by blocks of smoke and fog.
+fn cornell_smoke() -> Result<()> {
+    let white = Arc::new(Lambertian::new(Box::new(Color::new(0.73, 0.73, 0.73))));
+    let (tall_box, short_box) = cornell_box_boxes(white);
+
+    let smoke = ConstantMedium::new(tall_box, 0.01, Box::new(Color::black()));
+    let fog = ConstantMedium::new(short_box, 0.01, Box::new(Color::white()));
+    let world = Tlas::new(vec![
+        TlasInstance::new(Arc::new(cornell_box_walls()), Matrix::identity()),
+        TlasInstance::new(Arc::new(smoke), Matrix::identity()),
+        TlasInstance::new(Arc::new(fog), Matrix::identity()),
+    ]);
+
+    cornell_box_camera().render(&world);
+
+    return Ok(());
+}
+
+fn cornell_clouds() -> Result<()> {
+    let white = Arc::new(Lambertian::new(Box::new(Color::new(0.73, 0.73, 0.73))));
+    let (tall_box, _) = cornell_box_boxes(white.clone());
+
+    let cloud = HeterogeneousMedium::new(
+        tall_box,
+        NoiseDensity::new(0.02, 0.05),
+        Box::new(Color::white()),
+        0.6,
+    );
+
+    let center = Point::new(380., 130., 200.);
+    let radius = 120.;
+    let offset = Vec3::new(radius, radius, radius);
+    let explosion_density = VoxelGrid::load(
+        "assets/explosion.vox",
+        Aabb::new(center - offset, center + offset),
+    )?;
+    let explosion = HeterogeneousMedium::new(
+        Sphere::new(center, radius, white),
+        explosion_density,
+        Box::new(Color::new(0.9, 0.6, 0.3)),
+        0.2,
+    );
+
+    let world = Tlas::new(vec![
+        TlasInstance::new(Arc::new(cornell_box_walls()), Matrix::identity()),
+        TlasInstance::new(Arc::new(cloud), Matrix::identity()),
+        TlasInstance::new(Arc::new(explosion), Matrix::identity()),
+    ]);
+
+    cornell_box_camera().render(&world);
+
+    return Ok(());
+}
+
+fn studio_spheres() -> Result<()> {
+    let floor = Arc::new(Lambertian::new(Box::new(Color::new(0.5, 0.5, 0.5))));
+    let diffuse = Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.1, 0.1))));
+    let glass = Arc::new(Dielectric::new(1.5).with_absorption(Color::new(0.5, 0.8, 0.6), 1.));
+    let metal = Arc::new(Conductor::aluminium(0.3).with_anisotropy(0.8));
+    let copper = Arc::new(Conductor::copper(0.15));
+    let frosted = Arc::new(RoughDielectric::new(1.5, 0.3));
+
+    let world = vec![
+        Sphere::new(Point::new(0., -1000., 0.), 1000., floor),
+        Sphere::new(Point::new(-2.2, 1., 0.), 1., diffuse),
+        Sphere::new(Point::new(0., 1., 0.), 1., glass),
+        Sphere::new(Point::new(2.2, 1., 0.), 1., metal),
+        Sphere::new(Point::new(1.1, 0.5, 1.8), 0.5, copper),
+        Sphere::new(Point::new(-1.1, 0.5, 1.8), 0.5, frosted),
+    ];
+
+    let look_from = Point::new(0., 2., 8.);
+    let look_at = Point::new(0., 1., 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(35.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::black(),
+    )
+    .with_environment(
+        EnvironmentMap::new("assets/studio.hdr", ColorSpace::Linear)?
+            .with_rotation(Degrees(90.))
+            .with_intensity(1.5),
+    );
+
+    camera.render(&Bvh::new(world));
+
+    return Ok(());
+}
+
+fn delta_lights() -> Result<()> {
+    let floor = Arc::new(Lambertian::new(Box::new(Color::new(0.7, 0.7, 0.7))));
+    let white = Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.8, 0.8))));
+
+    let world = vec![
+        Sphere::new(Point::new(0., -1000., 0.), 1000., floor),
+        Sphere::new(Point::new(-2.2, 1., 0.), 1., white.clone()),
+        Sphere::new(Point::new(0., 1., 0.), 1., white.clone()),
+        Sphere::new(Point::new(2.2, 1., 0.), 1., white),
+    ];
+
+    let look_from = Point::new(0., 3., 9.);
+    let look_at = Point::new(0., 1., 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(35.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::new(0.02, 0.02, 0.03),
+    )
+    .with_light(PointLight::new(
+        Point::new(-2.2, 3.5, 2.),
+        Color::new(6., 4., 2.),
+    ))
+    .with_light(SpotLight::new(
+        Point::new(2.2, 5., 1.),
+        Point::new(2.2, 0., 0.),
+        Color::new(10., 10., 14.),
+        Degrees(15.),
+        Degrees(25.),
+    ))
+    .with_light(DirectionalLight::new(
+        Vec3::new(-1., 2., 1.),
+        Color::new(0.3, 0.3, 0.3),
+    ));
+
+    camera.render(&Bvh::new(world));
+
+    return Ok(());
+}
+
+fn ies_downlights() -> Result<()> {
+    let white = Arc::new(Lambertian::new(Box::new(Color::new(0.73, 0.73, 0.73))));
+    let profile = IesProfile::load("assets/downlight.ies")?;
+
+    let mut world = vec![
+        Quad::new(
+            Point::new(-6., 0., -3.),
+            Vec3::new(12., 0., 0.),
+            Vec3::new(0., 0., 6.),
+            white.clone(),
+        ),
+        Quad::new(
+            Point::new(-6., 0., -3.),
+            Vec3::new(0., 4., 0.),
+            Vec3::new(12., 0., 0.),
+            white,
+        ),
+    ];
+    for x in [-3., 0., 3.] {
+        // Facing down, with the profile pointing its axis along the normal
+        let size = 0.3;
+        let light_area = size * size;
+        let light = Arc::new(
+            DiffuseLight::new(Box::new(Color::new(1., 0.85, 0.7)))
+                .one_sided()
+                .with_power(40., light_area)
+                .with_profile(profile.clone()),
+        );
+        world.push(Quad::new(
+            Point::new(x - size / 2., 3.9, -2.5),
+            Vec3::new(size, 0., 0.),
+            Vec3::new(0., 0., size),
+            light,
+        ));
+    }
+
+    let look_from = Point::new(0., 2., 9.);
+    let look_at = Point::new(0., 1.8, 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(40.),
+        400,
+        look_from,
+        look_at,
+        up,
+        200,
+        Color::black(),
+    );
+
+    camera.render(&Bvh::new(world));
+
+    return Ok(());
 }
 
-fn parse_triangle<'a>(line: &str, material: &'a dyn Material) -> Triangle<'a> {
-    let values: Vec<f32> = line
+/// Parses a line of a mesh file, holding the x, y and z coordinates of the three vertices.
+fn parse_triangle(line: &str, material: Arc<dyn Material>) -> Result<Triangle, String> {
+    let values = line
         .split_whitespace()
-        .filter_map(|s| s.parse().ok())
-        .collect();
+        .map(|value| {
+            value
+                .parse::<f32>()
+                .map_err(|_| format!("invalid coordinate `{value}`"))
+        })
+        .collect::<Result<Vec<f32>, _>>()?;
+    if values.len() != 9 {
+        return Err(format!("expected 9 coordinates, found {}", values.len()));
+    }
 
-    return Triangle::new(
+    return Ok(Triangle::new(
         Point::new(values[0], values[1], values[2]),
         Point::new(values[3], values[4], values[5]),
         Point::new(values[6], values[7], values[8]),
         material,
-    );
+    ));
 }
 
-fn read_file<'a>(file_name: &str, material: &'a dyn Material) -> Vec<Triangle<'a>> {
-    let file = BufReader::new(File::open(file_name).unwrap());
+fn read_file(file_name: &str, material: Arc<dyn Material>) -> Result<Vec<Triangle>> {
+    let file = File::open(file_name).map_err(|error| Error::io(file_name, error))?;
 
-    return file
+    return BufReader::new(file)
         .lines()
-        .map(|line| parse_triangle(&line.unwrap(), material))
+        .enumerate()
+        .map(|(index, line)| {
+            let line = line.map_err(|error| Error::io(file_name, error))?;
+            return parse_triangle(&line, material.clone())
+                .map_err(|message| Error::invalid(file_name, Some(index + 1), message));
+        })
         .collect();
 }
 
-fn scene_robot() {
-    let material = Rc::new(Lambertian::new(Box::new(Color::new(0.8, 0.8, 0.))));
-    let triangles = read_file("assets/unity.tri", material.as_ref());
+/// Returns the BVH of the mesh in `file_name`, loaded from the mesh's cache file if it is up
+/// to date. Otherwise, the BVH is built from the mesh and written to the cache file.
+fn load_mesh(file_name: &str, material: Arc<dyn Material>) -> Result<Bvh<Triangle>> {
+    let source = fs::read(file_name).map_err(|error| Error::io(file_name, error))?;
+    let source_hash = hash_bytes(&source);
+    let cache_file_name = format!("{file_name}.bvh");
+
+    match Bvh::load(&cache_file_name, source_hash, material.clone()) {
+        Ok(Some(bvh)) => return Ok(bvh),
+        Ok(None) => println!("BVH cache {cache_file_name} is stale"),
+        Err(error) => println!("Could not load BVH cache: {error}"),
+    }
+
+    let bvh = Bvh::new(read_file(file_name, material)?);
+    if let Err(error) = bvh.save(&cache_file_name, source_hash) {
+        println!("Could not write BVH cache: {error}");
+    }
+    return Ok(bvh);
+}
 
-    println!("Building BVH");
+fn scene_robot() -> Result<()> {
+    let material = Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.8, 0.))));
+
+    println!("Loading BVH");
     let bvh_start_time = Instant::now();
-    let mut bvh = Bvh::new(triangles);
+    let mut bvh = load_mesh("assets/unity.tri", material.clone())?;
     println!(
-        "Wall time to build BVH: {:.1} ms",
+        "Wall time to load BVH: {:.1} ms",
         bvh_start_time.elapsed().as_millis()
     );
 
@@ -360,12 +821,14 @@ fn scene_robot() {
         100,
         Color::new(0.7, 0.8, 1.),
     );
-    camera.render(&bvh);
+    camera.render(&Bvh4::from(bvh));
+
+    return Ok(());
 }
 
-fn armadillos() {
-    let material = Rc::new(Lambertian::new(Box::new(Color::new(0.8, 0.8, 0.))));
-    let triangles = read_file("assets/armadillo.tri", material.as_ref());
+fn armadillos() -> Result<()> {
+    let material = Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.8, 0.))));
+    let bvh = Arc::new(load_mesh("assets/armadillo.tri", material)?);
 
     let look_from = Point::new(0., 0., -8.);
     let look_at = Point::new(0., 0., -1.);
@@ -381,18 +844,496 @@ fn armadillos() {
         Color::new(0.7, 0.8, 1.),
     );
 
-    let bvh = Bvh::new(triangles);
     let bvh_instance = BVHInstance::new(
-        &bvh,
+        bvh.clone(),
         Matrix::identity().scale(0.3, 2., 1.).translate(-2., 0., 0.),
     );
-    let bvh_instance2 = BVHInstance::new(&bvh, Matrix::identity().translate(2., 0., 0.));
-    let bvh_instance3 = BVHInstance::new(&bvh, Matrix::identity().rotate_y(Degrees(-45.)));
+    let bvh_instance2 = BVHInstance::new(bvh.clone(), Matrix::identity().translate(2., 0., 0.));
+    let bvh_instance3 = BVHInstance::new(bvh, Matrix::identity().rotate_y(Degrees(-45.)));
     // let tlas = Bvh::new(vec![bvh_instance, bvh_instance2, bvh_instance3]);
     // let tlas = Bvh::new(vec![bvh_instance3]);
-    let tlas = Bvh::new(vec![bvh_instance, bvh_instance2, bvh_instance3]);
+    let tlas = Bvh4::new(vec![bvh_instance, bvh_instance2, bvh_instance3]);
     camera.render(&tlas);
     // camera.render(&bvh_instance2);
+
+    return Ok(());
+}
+
+/// Rows of armadillos, demonstrating multi-level instancing:
+/// the crowd holds instances of a row, which holds instances of the mesh.
+/// Each row is recolored, and the back row is only seen in the mirror floor.
+fn armadillo_crowd() -> Result<()> {
+    let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.8, 0.))));
+    let armadillo = Arc::new(load_mesh("assets/armadillo.tri", material.clone())?);
+
+    let row = Arc::new(Bvh::new(
+        [-2., 0., 2.]
+            .into_iter()
+            .map(|x| {
+                BVHInstance::new(
+                    armadillo.clone(),
+                    Matrix::identity().scale(0.5, 0.5, 0.5).translate(x, 0., 0.),
+                )
+            })
+            .collect(),
+    ));
+    let front_row = BVHInstance::new(row, Matrix::identity().rotate_y(Degrees(10.)));
+    let row_colors = [
+        Color::new(0.8, 0.8, 0.),
+        Color::new(0.8, 0.2, 0.2),
+        Color::new(0.2, 0.4, 0.8),
+        Color::new(0.2, 0.7, 0.3),
+    ];
+    let rows = row_colors
+        .into_iter()
+        .enumerate()
+        .map(|(i, color)| {
+            let row_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(color)));
+            let row = front_row
+                .transformed(Matrix::identity().translate(0.5 * i as f32, 0., 2. * i as f32))
+                .with_material(MaterialOverride::Remap(vec![(
+                    material.clone(),
+                    row_material,
+                )]));
+            match i == row_colors.len() - 1 {
+                true => row.with_visibility(Visibility {
+                    camera: false,
+                    ..Visibility::ALL
+                }),
+                false => row,
+            }
+        })
+        .collect();
+
+    let floor_height = 0.5 * armadillo.bounds().min.y;
+    let mirror = Arc::new(Conductor::from_reflectance(Color::new(0.8, 0.8, 0.8), 0.));
+    let floor = Bvh::new(vec![Quad::new(
+        Point::new(-20., floor_height, -20.),
+        Vec3::new(40., 0., 0.),
+        Vec3::new(0., 0., 40.),
+        mirror,
+    )]);
+    let crowd = Tlas::new(vec![
+        TlasInstance::new(Arc::new(Bvh4::new(rows)), Matrix::identity()),
+        TlasInstance::new(Arc::new(floor), Matrix::identity()),
+    ]);
+
+    let look_from = Point::new(0., 2., -8.);
+    let look_at = Point::new(0., 0., -1.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(40.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::new(0.7, 0.8, 1.),
+    )
+    .with_environment(PreethamSky::new(Vec3::new(-0.5, 0.3, -1.), 2.5));
+    camera.render(&crowd);
+
+    return Ok(());
+}
+
+fn twisted_armadillo() -> Result<()> {
+    let material = Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.8, 0.))));
+    let mut bvh = load_mesh("assets/armadillo.tri", material.clone())?;
+
+    // Twist the mesh around the Y axis, as a single frame of a vertex animation
+    let twist = |point: Point| {
+        let (sin, cos) = (0.3 * point.y).sin_cos();
+        Point::new(
+            cos * point.x + sin * point.z,
+            point.y,
+            -sin * point.x + cos * point.z,
+        )
+    };
+    for triangle in bvh.hittables_mut() {
+        triangle.set_vertices(twist(triangle.a), twist(triangle.b), twist(triangle.c));
+    }
+
+    let refit_start_time = Instant::now();
+    let rebuilt = bvh.refit_or_rebuild(1.5);
+    println!(
+        "Wall time to {} BVH: {:.1} ms",
+        if rebuilt { "rebuild" } else { "refit" },
+        refit_start_time.elapsed().as_millis()
+    );
+
+    let look_from = Point::new(0., 0., -8.);
+    let look_at = Point::new(0., 0., -1.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(30.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::new(0.7, 0.8, 1.),
+    );
+    camera.render(&bvh);
+
+    return Ok(());
+}
+
+fn principled_materials() -> Result<()> {
+    let floor = Arc::new(Lambertian::new(Box::new(Color::new(0.5, 0.5, 0.5))));
+    let car_paint = Arc::new(
+        Principled::new(Box::new(Color::new(0.6, 0.05, 0.05)))
+            .with_roughness(0.4)
+            .with_clearcoat(1., 0.05),
+    );
+    let patchy_metal = Arc::new(
+        Principled::new(Box::new(Color::new(0.9, 0.6, 0.4)))
+            .with_metallic(CheckeredTexture::new(0.25, Box::new(1.), Box::new(0.)))
+            .with_roughness(NoiseTexture::new(4.)),
+    );
+    let glass = Arc::new(
+        Principled::new(Box::new(Color::new(0.8, 0.9, 1.)))
+            .with_transmission(1.)
+            .with_roughness(0.1),
+    );
+    let velvet = Arc::new(
+        Principled::new(Box::new(Color::new(0.3, 0.05, 0.4)))
+            .with_roughness(1.)
+            .with_sheen(1.),
+    );
+    let wax = Arc::new(
+        Principled::new(Box::new(Color::new(0.9, 0.6, 0.5)))
+            .with_subsurface(1.)
+            .with_specular(0.3),
+    );
+
+    let world = vec![
+        Sphere::new(Point::new(0., -1000., 0.), 1000., floor),
+        Sphere::new(Point::new(-4.4, 1., 0.), 1., car_paint),
+        Sphere::new(Point::new(-2.2, 1., 0.), 1., patchy_metal),
+        Sphere::new(Point::new(0., 1., 0.), 1., glass),
+        Sphere::new(Point::new(2.2, 1., 0.), 1., velvet),
+        Sphere::new(Point::new(4.4, 1., 0.), 1., wax),
+    ];
+
+    let look_from = Point::new(0., 2.5, 12.);
+    let look_at = Point::new(0., 1., 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(35.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::black(),
+    )
+    .with_environment(
+        EnvironmentMap::new("assets/studio.hdr", ColorSpace::Linear)?.with_rotation(Degrees(90.)),
+    );
+
+    camera.render(&Bvh::new(world));
+
+    return Ok(());
+}
+
+fn composite_materials() -> Result<()> {
+    let floor = Arc::new(Lambertian::new(Box::new(Color::new(0.5, 0.5, 0.5))));
+    let dirty_copper = Arc::new(MixMaterial::new(
+        Arc::new(Conductor::copper(0.2)),
+        Arc::new(Lambertian::new(Box::new(Color::new(0.2, 0.15, 0.1)))),
+        NoiseTexture::new(3.),
+    ));
+    let varnished_wood = Arc::new(LayeredMaterial::new(
+        Arc::new(Conductor::from_reflectance(Color::white(), 0.1)),
+        Arc::new(Lambertian::new(Box::new(CheckeredTexture::new(
+            0.2,
+            Box::new(Color::new(0.4, 0.2, 0.08)),
+            Box::new(Color::new(0.3, 0.14, 0.05)),
+        )))),
+        1.5,
+    ));
+    let label = Arc::new(TwoSidedMaterial::new(
+        Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.1, 0.1)))),
+        Arc::new(Lambertian::new(Box::new(Color::new(0.9, 0.9, 0.9)))),
+    ));
+
+    let spheres = vec![
+        Sphere::new(Point::new(0., -1000., 0.), 1000., floor),
+        Sphere::new(Point::new(-2.2, 1., 0.), 1., dirty_copper),
+        Sphere::new(Point::new(0., 1., 0.), 1., varnished_wood),
+    ];
+    // A card turned towards the camera, red on the front and white on the back
+    let card = vec![Quad::new(
+        Point::new(1.4, 0.2, 0.5),
+        Vec3::new(1.4, 0., -0.6),
+        Vec3::new(0., 1.6, 0.),
+        label,
+    )];
+    let world = Tlas::new(vec![
+        TlasInstance::new(Arc::new(Bvh::new(spheres)), Matrix::identity()),
+        TlasInstance::new(Arc::new(Bvh::new(card)), Matrix::identity()),
+    ]);
+
+    let look_from = Point::new(0., 2., 8.);
+    let look_at = Point::new(0., 1., 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(35.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::black(),
+    )
+    .with_environment(
+        EnvironmentMap::new("assets/studio.hdr", ColorSpace::Linear)?.with_rotation(Degrees(90.)),
+    );
+
+    camera.render(&world);
+
+    return Ok(());
+}
+
+fn surface_detail() -> Result<()> {
+    let tiles = Arc::new(
+        NormalMap::new(
+            Arc::new(Lambertian::new(Box::new(Color::new(0.6, 0.55, 0.5)))),
+            Box::new(
+                ImageTexture::new("assets/tiles_normal.png", ColorSpace::Raw)?.with_scale(4., 4.),
+            ),
+        )
+        .with_strength(0.8),
+    );
+    let stucco = Arc::new(BumpMap::new(
+        Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.8, 0.75)))),
+        Box::new(NoiseTexture::new(8.)),
+        0.02,
+    ));
+    let brushed_metal = Arc::new(Conductor::aluminium(0.4).with_anisotropy(0.9));
+
+    // A floor of two triangles, over which the tile texture repeats four times each way
+    let corners = [
+        Point::new(-6., 0., 6.),
+        Point::new(6., 0., 6.),
+        Point::new(6., 0., -6.),
+        Point::new(-6., 0., -6.),
+    ];
+    let floor = vec![
+        Triangle::new(corners[0], corners[2], corners[1], tiles.clone()).with_uvs([
+            (0., 0.),
+            (1., 1.),
+            (1., 0.),
+        ]),
+        Triangle::new(corners[0], corners[3], corners[2], tiles).with_uvs([
+            (0., 0.),
+            (0., 1.),
+            (1., 1.),
+        ]),
+    ];
+    let spheres = vec![
+        Sphere::new(Point::new(-1.2, 1., 0.), 1., stucco),
+        Sphere::new(Point::new(1.2, 1., 0.), 1., brushed_metal),
+    ];
+    let world = Tlas::new(vec![
+        TlasInstance::new(Arc::new(Bvh::new(floor)), Matrix::identity()),
+        TlasInstance::new(Arc::new(Bvh::new(spheres)), Matrix::identity()),
+    ]);
+
+    let look_from = Point::new(0., 3., 7.);
+    let look_at = Point::new(0., 0.8, 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(40.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::black(),
+    )
+    .with_environment(
+        EnvironmentMap::new("assets/studio.hdr", ColorSpace::Linear)?.with_rotation(Degrees(90.)),
+    );
+
+    camera.render(&world);
+
+    return Ok(());
+}
+
+fn cutouts() -> Result<()> {
+    let floor = Arc::new(Lambertian::new(Box::new(Color::new(0.5, 0.5, 0.5))));
+    let steel = Arc::new(Conductor::aluminium(0.3));
+    let leaf = Arc::new(TwoSidedMaterial::new(
+        Arc::new(Lambertian::new(Box::new(Color::new(0.2, 0.5, 0.1)))),
+        Arc::new(Lambertian::new(Box::new(Color::new(0.3, 0.6, 0.15)))),
+    ));
+    let orange = Arc::new(Lambertian::new(Box::new(Color::new(0.9, 0.4, 0.1))));
+
+    // A perforated screen, a foliage card and a sphere dissolving into noise
+    let perforations: Arc<dyn Texture> =
+        Arc::new(CheckeredTexture::new(0.08, Box::new(1.), Box::new(0.)));
+    // Without mip levels, the leaf keeps its crisp outline from afar
+    let leaf_mask: Arc<dyn Texture> = Arc::new(
+        ImageTexture::new("assets/leaf_mask.png", ColorSpace::Raw)?
+            .with_wrap(WrapMode::Clamp)
+            .with_filter(TextureFilter::Bilinear),
+    );
+    let screen = vec![Quad::new(
+        Point::new(-3.5, 0., -0.5),
+        Vec3::new(2.5, 0., 0.5),
+        Vec3::new(0., 2.5, 0.),
+        steel,
+    )
+    .with_opacity(perforations)];
+    let card = vec![
+        Triangle::new(
+            Point::new(-0.5, 0.2, 0.),
+            Point::new(1.5, 0.2, 0.),
+            Point::new(1.5, 2.2, 0.),
+            leaf.clone(),
+        )
+        .with_uvs([(0., 0.), (1., 0.), (1., 1.)])
+        .with_opacity(leaf_mask.clone()),
+        Triangle::new(
+            Point::new(-0.5, 0.2, 0.),
+            Point::new(1.5, 2.2, 0.),
+            Point::new(-0.5, 2.2, 0.),
+            leaf,
+        )
+        .with_uvs([(0., 0.), (1., 1.), (0., 1.)])
+        .with_opacity(leaf_mask),
+    ];
+    let spheres = vec![
+        Sphere::new(Point::new(0., -1000., 0.), 1000., floor),
+        Sphere::new(Point::new(3., 1., 0.), 1., orange)
+            .with_opacity(Arc::new(NoiseTexture::new(3.))),
+    ];
+    let world = Tlas::new(vec![
+        TlasInstance::new(Arc::new(Bvh::new(screen)), Matrix::identity()),
+        TlasInstance::new(Arc::new(Bvh::new(card)), Matrix::identity()),
+        TlasInstance::new(Arc::new(Bvh::new(spheres)), Matrix::identity()),
+    ]);
+
+    let look_from = Point::new(0., 2., 9.);
+    let look_at = Point::new(0., 1., 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(40.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::black(),
+    )
+    .with_environment(
+        EnvironmentMap::new("assets/studio.hdr", ColorSpace::Linear)?.with_rotation(Degrees(90.)),
+    );
+
+    camera.render(&world);
+
+    return Ok(());
+}
+
+fn procedural_textures() -> Result<()> {
+    let stone = ColorRamp::new(vec![
+        (0., Color::new(0.1, 0.1, 0.1)),
+        (0.05, Color::new(0.5, 0.45, 0.4)),
+        (0.6, Color::new(0.65, 0.6, 0.55)),
+    ]);
+    let flagstones = VoronoiTexture::new(0.8)
+        .with_pattern(VoronoiPattern::Edges)
+        .with_ramp(stone);
+    let clouds = FbmTexture::new(2.)
+        .with_fbm(Fbm::new().with_octaves(8).with_gain(0.55))
+        .with_ramp(ColorRamp::between(
+            Color::new(0.1, 0.3, 0.8),
+            Color::new(1., 1., 1.),
+        ));
+    let fire = TurbulenceTexture::new(1.5)
+        .with_fbm(Fbm::new().with_lacunarity(2.5))
+        .with_ramp(ColorRamp::new(vec![
+            (0., Color::new(1., 0.9, 0.3)),
+            (0.25, Color::new(0.9, 0.3, 0.05)),
+            (0.6, Color::new(0.1, 0.02, 0.02)),
+        ]));
+    let marble = MarbleTexture::new(3.)
+        .with_fbm(Fbm::new().with_octaves(6))
+        .with_distortion(6.)
+        .with_ramp(ColorRamp::between(
+            Color::new(0.9, 0.9, 0.85),
+            Color::new(0.2, 0.25, 0.2),
+        ));
+    let wood = WoodTexture::new(6.)
+        .with_fbm(Fbm::new().with_octaves(3))
+        .with_distortion(0.3)
+        .with_ramp(ColorRamp::between(
+            Color::new(0.6, 0.4, 0.2),
+            Color::new(0.3, 0.15, 0.05),
+        ));
+    // Cells swirled into one another
+    let warped_cells = DomainWarp::new(Box::new(VoronoiTexture::new(2.)), 1., 0.4)
+        .with_fbm(Fbm::new().with_octaves(4));
+
+    let world = vec![
+        Sphere::new(
+            Point::new(0., -1000., 0.),
+            1000.,
+            Arc::new(Lambertian::new(Box::new(flagstones))),
+        ),
+        Sphere::new(
+            Point::new(-4.4, 1., 0.),
+            1.,
+            Arc::new(Lambertian::new(Box::new(clouds))),
+        ),
+        Sphere::new(
+            Point::new(-2.2, 1., 0.),
+            1.,
+            Arc::new(DiffuseLight::new(Box::new(fire))),
+        ),
+        Sphere::new(
+            Point::new(0., 1., 0.),
+            1.,
+            Arc::new(Principled::new(Box::new(marble)).with_roughness(0.2)),
+        ),
+        Sphere::new(
+            Point::new(2.2, 1., 0.),
+            1.,
+            Arc::new(Lambertian::new(Box::new(wood))),
+        ),
+        Sphere::new(
+            Point::new(4.4, 1., 0.),
+            1.,
+            Arc::new(Lambertian::new(Box::new(warped_cells))),
+        ),
+    ];
+
+    let look_from = Point::new(0., 2.5, 12.);
+    let look_at = Point::new(0., 1., 0.);
+    let up = Vec3::new(0., 1., 0.);
+    let camera = Camera::new(
+        16. / 9.,
+        Degrees(35.),
+        400,
+        look_from,
+        look_at,
+        up,
+        100,
+        Color::black(),
+    )
+    .with_environment(PreethamSky::new(Vec3::new(1., 0.6, 0.4), 3.))
+    .with_medium(GlobalMedium::new(0.03, 10., Color::new(0.9, 0.9, 0.9), 0.7));
+
+    camera.render(&Bvh::new(world));
+
+    return Ok(());
 }
 
 fn main() {
@@ -400,7 +1341,7 @@ fn main() {
     let start_time = Instant::now();
 
     let scene = 8;
-    match scene {
+    let result = match scene {
         1 => basic_scene(),
         2 => scene_robot(),
         3 => checkered_spheres(),
@@ -410,7 +1351,26 @@ fn main() {
         7 => simple_light(),
         8 => cornell_box(),
         9 => armadillos(),
+        10 => twisted_armadillo(),
+        11 => armadillo_crowd(),
+        12 => cornell_smoke(),
+        13 => cornell_clouds(),
+        14 => studio_spheres(),
+        15 => delta_lights(),
+        16 => ies_downlights(),
+        17 => principled_materials(),
+        18 => composite_materials(),
+        19 => surface_detail(),
+        20 => cutouts(),
+        21 => procedural_textures(),
+        22 => visibility_masks(),
+        23 => rolling_ball(),
+        24 => texture_wrapping(),
         _ => basic_scene(),
+    };
+    if let Err(error) = result {
+        eprintln!("Could not set up scene {scene}: {error}");
+        process::exit(1);
     }
 
     println!("Wall time: {:.1} s", start_time.elapsed().as_secs_f64());